@@ -0,0 +1,315 @@
+use deno_core::{
+    anyhow::{anyhow, Context},
+    error::AnyError,
+    serde_json, ModuleSpecifier,
+};
+use deno_graph::ModuleGraph;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt,
+};
+
+const BASE64_CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// The bytes of a bundle attributed to the modules they were generated from.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SizeReport {
+    pub total_bytes: usize,
+    /// Bytes not mapped to any module, such as the glue added by the bundler.
+    pub unmapped_bytes: usize,
+    /// Every module of the bundle, largest first.
+    pub modules: Vec<ModuleSize>,
+    /// The modules aggregated by host and package, largest first.
+    pub packages: Vec<PackageSize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModuleSize {
+    pub specifier: String,
+    pub bytes: usize,
+    /// The modules importing this module.
+    pub importers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageSize {
+    pub package: String,
+    pub bytes: usize,
+    pub modules: usize,
+}
+
+#[derive(Deserialize)]
+struct RawSourceMap {
+    sources: Vec<String>,
+    mappings: String,
+}
+
+/// Attribute the bytes of `code` to the modules of `graph`, using the source map
+/// generated along with it.
+pub fn analyze(code: &str, source_map: &str, graph: &ModuleGraph) -> Result<SizeReport, AnyError> {
+    let (module_bytes, unmapped_bytes) = attribute_bytes(code, source_map)?;
+
+    let mut importers: HashMap<ModuleSpecifier, BTreeSet<String>> = HashMap::new();
+    for module in graph.modules() {
+        for dep in module.dependencies.values() {
+            if let Some(specifier) = dep.get_code() {
+                importers
+                    .entry(graph.resolve(specifier))
+                    .or_default()
+                    .insert(module.specifier.to_string());
+            }
+        }
+    }
+
+    let mut modules: Vec<ModuleSize> = module_bytes
+        .into_iter()
+        .map(|(specifier, bytes)| {
+            let importers = ModuleSpecifier::parse(&specifier)
+                .ok()
+                .and_then(|s| importers.get(&s))
+                .map(|importers| importers.iter().cloned().collect())
+                .unwrap_or_default();
+            ModuleSize {
+                specifier,
+                bytes,
+                importers,
+            }
+        })
+        .collect();
+    modules.sort_by(|a, b| {
+        b.bytes
+            .cmp(&a.bytes)
+            .then_with(|| a.specifier.cmp(&b.specifier))
+    });
+
+    let mut packages: BTreeMap<String, PackageSize> = BTreeMap::new();
+    for module in &modules {
+        let package = package_name(&module.specifier);
+        let entry = packages.entry(package.clone()).or_insert(PackageSize {
+            package,
+            bytes: 0,
+            modules: 0,
+        });
+        entry.bytes += module.bytes;
+        entry.modules += 1;
+    }
+    let mut packages: Vec<PackageSize> = packages.into_values().collect();
+    packages.sort_by(|a, b| b.bytes.cmp(&a.bytes));
+
+    Ok(SizeReport {
+        total_bytes: code.len(),
+        unmapped_bytes,
+        modules,
+        packages,
+    })
+}
+
+impl fmt::Display for SizeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let percent = |bytes: usize| {
+            if self.total_bytes == 0 {
+                0.0
+            } else {
+                bytes as f64 * 100.0 / self.total_bytes as f64
+            }
+        };
+        writeln!(f, "{:>10}  {:>6}  Package", "Bytes", "%")?;
+        for package in &self.packages {
+            writeln!(
+                f,
+                "{:>10}  {:>5.1}%  {} ({} modules)",
+                package.bytes,
+                percent(package.bytes),
+                package.package,
+                package.modules
+            )?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{:>10}  {:>6}  Module", "Bytes", "%")?;
+        for module in &self.modules {
+            writeln!(
+                f,
+                "{:>10}  {:>5.1}%  {}",
+                module.bytes,
+                percent(module.bytes),
+                module.specifier
+            )?;
+            if let Some(importer) = module.importers.first() {
+                let others = module.importers.len() - 1;
+                if others > 0 {
+                    writeln!(f, "{:>20}imported by {} and {} more", "", importer, others)?;
+                } else {
+                    writeln!(f, "{:>20}imported by {}", "", importer)?;
+                }
+            }
+        }
+        writeln!(
+            f,
+            "{:>10}  {:>5.1}%  (unmapped)",
+            self.unmapped_bytes,
+            percent(self.unmapped_bytes)
+        )?;
+        write!(f, "{:>10}  100.0%  total", self.total_bytes)
+    }
+}
+
+/// Group modules by host and package, e.g. `deno.land/std@0.196.0` or
+/// `esm.sh/preact@10.16.0`. Local modules are grouped together.
+fn package_name(specifier: &str) -> String {
+    let Ok(url) = ModuleSpecifier::parse(specifier) else {
+        return specifier.to_string();
+    };
+    match url.scheme() {
+        "http" | "https" => {
+            let mut package = url.host_str().unwrap_or_default().to_string();
+            let mut segments = url.path_segments().into_iter().flatten();
+            while let Some(segment) = segments.next() {
+                package.push('/');
+                package.push_str(segment);
+                if segment.starts_with('@') && !segment[1..].contains('@') {
+                    // A scoped package name, continue to the versioned name.
+                    continue;
+                }
+                if segment.contains('@') {
+                    break;
+                }
+            }
+            if package.contains('@') {
+                package
+            } else {
+                url.host_str().unwrap_or_default().to_string()
+            }
+        }
+        "file" => "(local)".to_string(),
+        "npm" => {
+            // `npm:/@scope/name@version/path`
+            let path = url.path().trim_start_matches('/');
+            let segments = if path.starts_with('@') { 2 } else { 1 };
+            let package: Vec<&str> = path.split('/').take(segments).collect();
+            format!("npm:{}", package.join("/"))
+        }
+        scheme => format!("({})", scheme),
+    }
+}
+
+/// Count the bytes of `code` generated from each source of `source_map`,
+/// returning them along with the count of unmapped bytes.
+fn attribute_bytes(
+    code: &str,
+    source_map: &str,
+) -> Result<(HashMap<String, usize>, usize), AnyError> {
+    let source_map: RawSourceMap =
+        serde_json::from_str(source_map).context("Invalid source map.")?;
+    let mut module_bytes: HashMap<String, usize> = HashMap::new();
+    let mut unmapped_bytes = 0;
+    let mut mapping_lines = source_map.mappings.split(';');
+    let mut source_index = 0i64;
+    for line in code.split_inclusive('\n') {
+        // (generated column, source index) of each segment on the line.
+        let mut segments = Vec::new();
+        let mut column = 0i64;
+        for segment in mapping_lines.next().unwrap_or_default().split(',') {
+            if segment.is_empty() {
+                continue;
+            }
+            let fields = decode_vlq(segment)?;
+            column += fields[0];
+            let source = if fields.len() >= 4 {
+                source_index += fields[1];
+                Some(source_index as usize)
+            } else {
+                None
+            };
+            segments.push((column, source));
+        }
+
+        let mut current = None;
+        let mut next_segment = segments.iter().peekable();
+        let mut position = 0i64;
+        for c in line.chars() {
+            while let Some((_, source)) = next_segment.next_if(|(col, _)| *col <= position) {
+                current = *source;
+            }
+            let bytes = c.len_utf8();
+            match current.and_then(|i| source_map.sources.get(i)) {
+                Some(source) if c != '\n' => {
+                    *module_bytes.entry(source.clone()).or_default() += bytes
+                }
+                _ => unmapped_bytes += bytes,
+            }
+            position += c.len_utf16() as i64;
+        }
+    }
+    Ok((module_bytes, unmapped_bytes))
+}
+
+/// Decode the base64 VLQ fields of a source map segment.
+pub(super) fn decode_vlq(segment: &str) -> Result<Vec<i64>, AnyError> {
+    let mut fields = Vec::new();
+    let mut value = 0i64;
+    let mut shift = 0;
+    for c in segment.bytes() {
+        let digit = BASE64_CHARS
+            .iter()
+            .position(|b| *b == c)
+            .ok_or_else(|| anyhow!("Invalid source map segment \"{}\".", segment))?
+            as i64;
+        value += (digit & 0b11111) << shift;
+        if digit & 0b100000 != 0 {
+            shift += 5;
+        } else {
+            fields.push(if value & 1 == 1 {
+                -(value >> 1)
+            } else {
+                value >> 1
+            });
+            value = 0;
+            shift = 0;
+        }
+    }
+    Ok(fields)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attribute_bytes_should_follow_the_source_map() {
+        let source_map = r#"{
+          "version": 3,
+          "sources": ["file:///a.ts", "file:///b.ts"],
+          "names": [],
+          "mappings": "AAAA,CCAA;ADAA"
+        }"#;
+        let (module_bytes, unmapped_bytes) = attribute_bytes("ab\ncd", source_map).unwrap();
+        assert_eq!(module_bytes["file:///a.ts"], 3);
+        assert_eq!(module_bytes["file:///b.ts"], 1);
+        assert_eq!(unmapped_bytes, 1);
+    }
+
+    #[test]
+    fn test_package_name() {
+        assert_eq!(
+            package_name("https://deno.land/std@0.196.0/http/server.ts"),
+            "deno.land/std@0.196.0"
+        );
+        assert_eq!(
+            package_name("https://esm.sh/@preact/signals@1.2.0/dist/index.js"),
+            "esm.sh/@preact/signals@1.2.0"
+        );
+        assert_eq!(
+            package_name("https://example.com/lib/mod.ts"),
+            "example.com"
+        );
+        assert_eq!(package_name("file:///app/main.ts"), "(local)");
+        assert_eq!(
+            package_name("npm:/@preact/signals@1.2.0/dist/signals.mjs"),
+            "npm:@preact/signals@1.2.0"
+        );
+    }
+}
@@ -0,0 +1,173 @@
+use flate2::{write::GzEncoder, Compression};
+use std::{fmt, io::Write};
+
+use super::analyze::SizeReport;
+use crate::utils::is_content_compressible;
+
+/// The content type bundles are served with.
+const BUNDLE_CONTENT_TYPE: &str = "application/javascript";
+
+/// Size limits for a bundle. Unset limits are not checked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SizeBudget {
+    /// The maximum size of the bundle in bytes.
+    pub max_bytes: Option<usize>,
+    /// The maximum size of the bundle once gzipped, in bytes.
+    pub max_gzip_bytes: Option<usize>,
+    /// The maximum number of bytes a single module may contribute.
+    pub max_module_bytes: Option<usize>,
+}
+
+/// A limit of a `SizeBudget` that a bundle exceeds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BudgetViolation {
+    Total {
+        bytes: usize,
+        limit: usize,
+    },
+    Gzip {
+        bytes: usize,
+        limit: usize,
+    },
+    Module {
+        specifier: String,
+        bytes: usize,
+        limit: usize,
+    },
+}
+
+impl fmt::Display for BudgetViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BudgetViolation::Total { bytes, limit } => {
+                write!(
+                    f,
+                    "bundle is {} bytes, over the {} byte budget",
+                    bytes, limit
+                )
+            }
+            BudgetViolation::Gzip { bytes, limit } => write!(
+                f,
+                "gzipped bundle is {} bytes, over the {} byte budget",
+                bytes, limit
+            ),
+            BudgetViolation::Module {
+                specifier,
+                bytes,
+                limit,
+            } => write!(
+                f,
+                "{} contributes {} bytes, over the {} byte per-module budget",
+                specifier, bytes, limit
+            ),
+        }
+    }
+}
+
+/// The error returned when a bundle exceeds its `SizeBudget`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BudgetExceeded(pub Vec<BudgetViolation>);
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The bundle exceeds its size budget:")?;
+        for violation in &self.0 {
+            write!(f, "\n  {}", violation)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+impl SizeBudget {
+    /// Whether checking the budget needs a `SizeReport` of the bundle.
+    pub fn needs_report(&self) -> bool {
+        self.max_module_bytes.is_some()
+    }
+
+    /// Check `code` against the budget. The per-module limit is only checked
+    /// when a `report` is given.
+    pub fn check(&self, code: &str, report: Option<&SizeReport>) -> Result<(), BudgetExceeded> {
+        let mut violations = Vec::new();
+        if let Some(limit) = self.max_bytes {
+            if code.len() > limit {
+                violations.push(BudgetViolation::Total {
+                    bytes: code.len(),
+                    limit,
+                });
+            }
+        }
+        if let Some(limit) = self.max_gzip_bytes {
+            let bytes = served_size(code.as_bytes());
+            if bytes > limit {
+                violations.push(BudgetViolation::Gzip { bytes, limit });
+            }
+        }
+        if let (Some(limit), Some(report)) = (self.max_module_bytes, report) {
+            for module in &report.modules {
+                if module.bytes > limit {
+                    violations.push(BudgetViolation::Module {
+                        specifier: module.specifier.clone(),
+                        bytes: module.bytes,
+                        limit,
+                    });
+                }
+            }
+        }
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(BudgetExceeded(violations))
+        }
+    }
+}
+
+/// The size of `code` as served: gzipped at the default level when the bundle
+/// content type is compressible, like the HTTP server does.
+pub fn served_size(code: &[u8]) -> usize {
+    if !is_content_compressible(BUNDLE_CONTENT_TYPE) {
+        return code.len();
+    }
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(code)
+        .and_then(|_| encoder.finish())
+        .map(|compressed| compressed.len())
+        .unwrap_or(code.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn size_budget_should_report_every_violation() {
+        let code = "console.log(\"hello world\");\n".repeat(100);
+        let budget = SizeBudget {
+            max_bytes: Some(100),
+            max_gzip_bytes: Some(10),
+            max_module_bytes: Some(1),
+        };
+        let violations = budget.check(&code, None).unwrap_err().0;
+        assert_eq!(violations.len(), 2);
+        assert_eq!(
+            violations[0],
+            BudgetViolation::Total {
+                bytes: code.len(),
+                limit: 100
+            }
+        );
+        assert!(matches!(
+            violations[1],
+            BudgetViolation::Gzip { limit: 10, .. }
+        ));
+
+        let budget = SizeBudget {
+            max_bytes: Some(code.len()),
+            max_gzip_bytes: Some(code.len()),
+            max_module_bytes: None,
+        };
+        assert!(budget.check(&code, None).is_ok());
+    }
+}
@@ -0,0 +1,247 @@
+use deno_core::ModuleSpecifier;
+use deno_graph::ModuleGraph;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    path::Path,
+};
+
+use super::external::Externals;
+
+/// The name of the manifest written next to the chunks.
+pub const CHUNK_MANIFEST_FILE: &str = "chunk-manifest.json";
+
+/// Describes the chunks written by `bundle_chunks`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkManifest {
+    /// Maps each entry point to the chunk file it is bundled into.
+    pub entries: BTreeMap<String, String>,
+    /// Every chunk written, keyed by its file name.
+    pub chunks: BTreeMap<String, ChunkInfo>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChunkInfo {
+    /// The module the chunk is bundled from.
+    pub root: String,
+    pub is_entry: bool,
+    /// Chunk files this chunk statically imports.
+    pub imports: Vec<String>,
+    /// Chunk files this chunk loads through `import()`.
+    pub dynamic_imports: Vec<String>,
+}
+
+/// Splits a module graph into chunks.
+///
+/// Every entry point and every target of a dynamic `import()` becomes the root
+/// of a chunk. A module statically reachable from more than one chunk is
+/// promoted to a chunk of its own, so it is only emitted once. External modules
+/// are never part of a chunk.
+pub struct ChunkPlan {
+    files: BTreeMap<ModuleSpecifier, String>,
+    /// Maps each entry point, as given, to the module it resolved to.
+    entries: BTreeMap<String, ModuleSpecifier>,
+    imports: BTreeMap<ModuleSpecifier, BTreeSet<ModuleSpecifier>>,
+    dynamic_imports: BTreeMap<ModuleSpecifier, BTreeSet<ModuleSpecifier>>,
+}
+
+impl ChunkPlan {
+    pub fn new(graph: &ModuleGraph, entries: &[ModuleSpecifier], externals: &Externals) -> Self {
+        let entries: BTreeMap<String, ModuleSpecifier> = entries
+            .iter()
+            .map(|e| (e.to_string(), graph.resolve(e)))
+            .collect();
+        let mut roots: BTreeSet<ModuleSpecifier> = entries.values().cloned().collect();
+        loop {
+            let mut owners: HashMap<ModuleSpecifier, ModuleSpecifier> = HashMap::new();
+            let mut new_roots = Vec::new();
+            for root in &roots {
+                walk_chunk(graph, externals, root, &roots, |dep, is_dynamic| {
+                    if roots.contains(dep) {
+                        return false;
+                    }
+                    if is_dynamic {
+                        new_roots.push(dep.clone());
+                        return false;
+                    }
+                    match owners.get(dep) {
+                        Some(owner) if owner != root => {
+                            new_roots.push(dep.clone());
+                            false
+                        }
+                        _ => {
+                            owners.insert(dep.clone(), root.clone());
+                            true
+                        }
+                    }
+                });
+            }
+            if new_roots.is_empty() {
+                break;
+            }
+            roots.extend(new_roots);
+        }
+
+        let mut imports = BTreeMap::new();
+        let mut dynamic_imports = BTreeMap::new();
+        for root in &roots {
+            let mut chunk_imports = BTreeSet::new();
+            let mut chunk_dynamic_imports = BTreeSet::new();
+            walk_chunk(graph, externals, root, &roots, |dep, is_dynamic| {
+                if is_dynamic {
+                    chunk_dynamic_imports.insert(dep.clone());
+                    false
+                } else if roots.contains(dep) {
+                    chunk_imports.insert(dep.clone());
+                    false
+                } else {
+                    true
+                }
+            });
+            imports.insert(root.clone(), chunk_imports);
+            dynamic_imports.insert(root.clone(), chunk_dynamic_imports);
+        }
+
+        let mut taken = HashSet::new();
+        let files = roots
+            .iter()
+            .map(|root| {
+                let file_name = if entries.values().any(|e| e == root) {
+                    entry_file_name(root, &mut taken)
+                } else {
+                    chunk_file_name(root)
+                };
+                (root.clone(), file_name)
+            })
+            .collect();
+
+        Self {
+            files,
+            entries,
+            imports,
+            dynamic_imports,
+        }
+    }
+
+    /// The root module of every chunk.
+    pub fn roots(&self) -> impl Iterator<Item = &ModuleSpecifier> {
+        self.files.keys()
+    }
+
+    pub fn file_name(&self, root: &ModuleSpecifier) -> &str {
+        &self.files[root]
+    }
+
+    /// The specifiers to rewrite when bundling the chunk rooted at `root`: every
+    /// other chunk root is imported from its chunk file instead of being inlined.
+    pub fn rewrites_for(&self, root: &ModuleSpecifier) -> HashMap<ModuleSpecifier, String> {
+        self.files
+            .iter()
+            .filter(|(specifier, _)| *specifier != root)
+            .map(|(specifier, file_name)| (specifier.clone(), format!("./{}", file_name)))
+            .collect()
+    }
+
+    pub fn manifest(&self) -> ChunkManifest {
+        let file_names = |specifiers: &BTreeSet<ModuleSpecifier>| {
+            specifiers
+                .iter()
+                .map(|s| self.files[s].clone())
+                .collect::<Vec<_>>()
+        };
+        let chunks = self
+            .files
+            .iter()
+            .map(|(root, file_name)| {
+                let info = ChunkInfo {
+                    root: root.to_string(),
+                    is_entry: self.entries.values().any(|e| e == root),
+                    imports: file_names(&self.imports[root]),
+                    dynamic_imports: file_names(&self.dynamic_imports[root]),
+                };
+                (file_name.clone(), info)
+            })
+            .collect();
+        let entries = self
+            .entries
+            .iter()
+            .map(|(entry, root)| (entry.clone(), self.files[root].clone()))
+            .collect();
+        ChunkManifest { entries, chunks }
+    }
+}
+
+/// Walk the modules statically bundled into the chunk rooted at `root`, calling
+/// `visit` for each dependency found. The walk descends into a dependency only
+/// when it is not a chunk root and `visit` returns `true`.
+fn walk_chunk(
+    graph: &ModuleGraph,
+    externals: &Externals,
+    root: &ModuleSpecifier,
+    roots: &BTreeSet<ModuleSpecifier>,
+    mut visit: impl FnMut(&ModuleSpecifier, bool) -> bool,
+) {
+    let mut seen = HashSet::new();
+    let mut stack = vec![root.clone()];
+    while let Some(specifier) = stack.pop() {
+        if !seen.insert(specifier.clone()) {
+            continue;
+        }
+        for (dep, is_dynamic) in dependencies(graph, &specifier) {
+            if dep == *root || externals.matches(dep.as_str()) {
+                continue;
+            }
+            if visit(&dep, is_dynamic) && !roots.contains(&dep) {
+                stack.push(dep);
+            }
+        }
+    }
+}
+
+fn dependencies(graph: &ModuleGraph, specifier: &ModuleSpecifier) -> Vec<(ModuleSpecifier, bool)> {
+    match graph.get(specifier) {
+        Some(module) => module
+            .dependencies
+            .values()
+            .filter_map(|dep| dep.get_code().map(|s| (graph.resolve(s), dep.is_dynamic)))
+            .collect(),
+        None => Vec::new(),
+    }
+}
+
+fn entry_file_name(specifier: &ModuleSpecifier, taken: &mut HashSet<String>) -> String {
+    let stem = Path::new(specifier.path())
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .filter(|s| !s.is_empty())
+        .unwrap_or("main")
+        .to_string();
+    let mut file_name = format!("{}.js", stem);
+    let mut i = 1;
+    while !taken.insert(file_name.clone()) {
+        file_name = format!("{}-{}.js", stem, i);
+        i += 1;
+    }
+    file_name
+}
+
+/// The file name of a shared or dynamically imported chunk, derived from the
+/// SHA-256 of its root so it stays the same across builds and toolchains.
+fn chunk_file_name(root: &ModuleSpecifier) -> String {
+    let hash = format!("{:x}", Sha256::digest(root.as_str().as_bytes()));
+    format!("chunk-{}.js", &hash[..16])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunk_file_name_should_be_stable() {
+        let root = ModuleSpecifier::parse("file:///app/routes/about.ts").unwrap();
+        assert_eq!(chunk_file_name(&root), "chunk-2d735e635d093ad6.js");
+    }
+}
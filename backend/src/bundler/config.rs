@@ -1,7 +1,199 @@
 use deno_core::{
+    anyhow::{bail, Context},
     error::AnyError,
     serde_json::{self, json, Value},
+    ModuleSpecifier,
 };
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// The project config files looked up next to the entry point and in each of
+/// its ancestor directories, in order of precedence.
+const CONFIG_FILE_NAMES: &[&str] = &["deno.json", "deno.jsonc", "tsconfig.json"];
+
+/// The compiler options which change the emitted code.
+const SUPPORTED_COMPILER_OPTIONS: &[&str] = &[
+    "checkJs",
+    "emitDecoratorMetadata",
+    "importsNotUsedAsValues",
+    "inlineSourceMap",
+    "inlineSources",
+    "sourceMap",
+    "jsx",
+    "jsxFactory",
+    "jsxFragmentFactory",
+    "jsxImportSource",
+];
+
+/// The type of the value of a compiler option.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OptionType {
+    Boolean,
+    String,
+    Number,
+    StringList,
+    Object,
+    List,
+}
+
+impl OptionType {
+    fn matches(self, value: &Value) -> bool {
+        match self {
+            OptionType::Boolean => value.is_boolean(),
+            OptionType::String => value.is_string(),
+            OptionType::Number => value.is_number(),
+            OptionType::StringList => value
+                .as_array()
+                .map_or(false, |values| values.iter().all(Value::is_string)),
+            OptionType::Object => value.is_object(),
+            OptionType::List => value.is_array(),
+        }
+    }
+
+    fn expected(self) -> &'static str {
+        match self {
+            OptionType::Boolean => "a boolean",
+            OptionType::String => "a string",
+            OptionType::Number => "a number",
+            OptionType::StringList => "an array of strings",
+            OptionType::Object => "an object",
+            OptionType::List => "an array",
+        }
+    }
+}
+
+/// Every compiler option of TypeScript and Deno. The ones which aren't in
+/// `SUPPORTED_COMPILER_OPTIONS` have no effect on bundling and are ignored.
+const KNOWN_COMPILER_OPTIONS: &[(&str, OptionType)] = &[
+    ("allowArbitraryExtensions", OptionType::Boolean),
+    ("allowImportingTsExtensions", OptionType::Boolean),
+    ("allowJs", OptionType::Boolean),
+    ("allowSyntheticDefaultImports", OptionType::Boolean),
+    ("allowUmdGlobalAccess", OptionType::Boolean),
+    ("allowUnreachableCode", OptionType::Boolean),
+    ("allowUnusedLabels", OptionType::Boolean),
+    ("alwaysStrict", OptionType::Boolean),
+    (
+        "assumeChangesOnlyAffectDirectDependencies",
+        OptionType::Boolean,
+    ),
+    ("baseUrl", OptionType::String),
+    ("charset", OptionType::String),
+    ("checkJs", OptionType::Boolean),
+    ("composite", OptionType::Boolean),
+    ("customConditions", OptionType::StringList),
+    ("declaration", OptionType::Boolean),
+    ("declarationDir", OptionType::String),
+    ("declarationMap", OptionType::Boolean),
+    ("diagnostics", OptionType::Boolean),
+    ("disableReferencedProjectLoad", OptionType::Boolean),
+    ("disableSizeLimit", OptionType::Boolean),
+    ("disableSolutionSearching", OptionType::Boolean),
+    (
+        "disableSourceOfProjectReferenceRedirect",
+        OptionType::Boolean,
+    ),
+    ("downlevelIteration", OptionType::Boolean),
+    ("emitBOM", OptionType::Boolean),
+    ("emitDeclarationOnly", OptionType::Boolean),
+    ("emitDecoratorMetadata", OptionType::Boolean),
+    ("erasableSyntaxOnly", OptionType::Boolean),
+    ("esModuleInterop", OptionType::Boolean),
+    ("exactOptionalPropertyTypes", OptionType::Boolean),
+    ("experimentalDecorators", OptionType::Boolean),
+    ("explainFiles", OptionType::Boolean),
+    ("extendedDiagnostics", OptionType::Boolean),
+    ("forceConsistentCasingInFileNames", OptionType::Boolean),
+    ("generateCpuProfile", OptionType::String),
+    ("generateTrace", OptionType::String),
+    ("ignoreDeprecations", OptionType::String),
+    ("importHelpers", OptionType::Boolean),
+    ("importsNotUsedAsValues", OptionType::String),
+    ("incremental", OptionType::Boolean),
+    ("inlineSourceMap", OptionType::Boolean),
+    ("inlineSources", OptionType::Boolean),
+    ("isolatedDeclarations", OptionType::Boolean),
+    ("isolatedModules", OptionType::Boolean),
+    ("jsx", OptionType::String),
+    ("jsxFactory", OptionType::String),
+    ("jsxFragmentFactory", OptionType::String),
+    ("jsxImportSource", OptionType::String),
+    ("jsxImportSourceTypes", OptionType::String),
+    ("jsxPrecompileSkipElements", OptionType::StringList),
+    ("keyofStringsOnly", OptionType::Boolean),
+    ("lib", OptionType::StringList),
+    ("libReplacement", OptionType::Boolean),
+    ("listEmittedFiles", OptionType::Boolean),
+    ("listFiles", OptionType::Boolean),
+    ("locale", OptionType::String),
+    ("mapRoot", OptionType::String),
+    ("maxNodeModuleJsDepth", OptionType::Number),
+    ("module", OptionType::String),
+    ("moduleDetection", OptionType::String),
+    ("moduleResolution", OptionType::String),
+    ("moduleSuffixes", OptionType::StringList),
+    ("newLine", OptionType::String),
+    ("noCheck", OptionType::Boolean),
+    ("noEmit", OptionType::Boolean),
+    ("noEmitHelpers", OptionType::Boolean),
+    ("noEmitOnError", OptionType::Boolean),
+    ("noErrorTruncation", OptionType::Boolean),
+    ("noFallthroughCasesInSwitch", OptionType::Boolean),
+    ("noImplicitAny", OptionType::Boolean),
+    ("noImplicitOverride", OptionType::Boolean),
+    ("noImplicitReturns", OptionType::Boolean),
+    ("noImplicitThis", OptionType::Boolean),
+    ("noImplicitUseStrict", OptionType::Boolean),
+    ("noLib", OptionType::Boolean),
+    ("noPropertyAccessFromIndexSignature", OptionType::Boolean),
+    ("noResolve", OptionType::Boolean),
+    ("noStrictGenericChecks", OptionType::Boolean),
+    ("noUncheckedIndexedAccess", OptionType::Boolean),
+    ("noUncheckedSideEffectImports", OptionType::Boolean),
+    ("noUnusedLocals", OptionType::Boolean),
+    ("noUnusedParameters", OptionType::Boolean),
+    ("out", OptionType::String),
+    ("outDir", OptionType::String),
+    ("outFile", OptionType::String),
+    ("paths", OptionType::Object),
+    ("plugins", OptionType::List),
+    ("preserveConstEnums", OptionType::Boolean),
+    ("preserveSymlinks", OptionType::Boolean),
+    ("preserveValueImports", OptionType::Boolean),
+    ("preserveWatchOutput", OptionType::Boolean),
+    ("pretty", OptionType::Boolean),
+    ("reactNamespace", OptionType::String),
+    ("removeComments", OptionType::Boolean),
+    ("resolveJsonModule", OptionType::Boolean),
+    ("resolvePackageJsonExports", OptionType::Boolean),
+    ("resolvePackageJsonImports", OptionType::Boolean),
+    ("rewriteRelativeImportExtensions", OptionType::Boolean),
+    ("rootDir", OptionType::String),
+    ("rootDirs", OptionType::StringList),
+    ("skipDefaultLibCheck", OptionType::Boolean),
+    ("skipLibCheck", OptionType::Boolean),
+    ("sourceMap", OptionType::Boolean),
+    ("sourceRoot", OptionType::String),
+    ("strict", OptionType::Boolean),
+    ("strictBindCallApply", OptionType::Boolean),
+    ("strictBuiltinIteratorReturn", OptionType::Boolean),
+    ("strictFunctionTypes", OptionType::Boolean),
+    ("strictNullChecks", OptionType::Boolean),
+    ("strictPropertyInitialization", OptionType::Boolean),
+    ("stripInternal", OptionType::Boolean),
+    ("suppressExcessPropertyErrors", OptionType::Boolean),
+    ("suppressImplicitAnyIndexErrors", OptionType::Boolean),
+    ("target", OptionType::String),
+    ("traceResolution", OptionType::Boolean),
+    ("tsBuildInfoFile", OptionType::String),
+    ("typeRoots", OptionType::StringList),
+    ("types", OptionType::StringList),
+    ("useDefineForClassFields", OptionType::Boolean),
+    ("useUnknownInCatchVariables", OptionType::Boolean),
+    ("verbatimModuleSyntax", OptionType::Boolean),
+];
 
 /// A structure for managing the configuration of TypeScript
 #[derive(Debug, Clone)]
@@ -17,12 +209,222 @@ impl TsConfig {
     pub fn merge(&mut self, value: &Value) {
         json_merge(&mut self.0, value);
     }
+
+    /// Merge the `compilerOptions` of a `deno.json` or `tsconfig.json` file into
+    /// the configuration.
+    pub fn merge_config_file(&mut self, path: &Path) -> Result<(), AnyError> {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("Unable to read config file {}", path.display()))?;
+        let config: Value =
+            serde_json::from_str(&strip_trailing_commas(&strip_json_comments(&text)))
+                .with_context(|| format!("Invalid config file {}", path.display()))?;
+        if let Some(compiler_options) = config.get("compilerOptions") {
+            let compiler_options = check_compiler_options(compiler_options)
+                .with_context(|| format!("Invalid compilerOptions in {}", path.display()))?;
+            self.merge(&compiler_options);
+        }
+        Ok(())
+    }
+}
+
+/// Find the project config file for `specifier`, looking in the directory of
+/// the module and then in each of its ancestors. Only local modules can have a
+/// config file.
+pub fn discover_config_file(specifier: &ModuleSpecifier) -> Option<PathBuf> {
+    let path = specifier.to_file_path().ok()?;
+    path.ancestors().skip(1).find_map(|dir| {
+        CONFIG_FILE_NAMES
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    })
+}
+
+/// Check that every compiler option is known and has the right type, and
+/// return the ones which affect the emitted code.
+fn check_compiler_options(compiler_options: &Value) -> Result<Value, AnyError> {
+    let Some(compiler_options) = compiler_options.as_object() else {
+        bail!("Expected compilerOptions to be an object.");
+    };
+    let mut supported = serde_json::Map::new();
+    let mut errors = Vec::new();
+    for (key, value) in compiler_options {
+        match KNOWN_COMPILER_OPTIONS.iter().find(|(name, _)| name == key) {
+            None => errors.push(format!("  {}: unknown compiler option", key)),
+            // `null` resets an option to its default.
+            Some((_, option_type)) if !value.is_null() && !option_type.matches(value) => {
+                errors.push(format!(
+                    "  {}: expected {}, found {}",
+                    key,
+                    option_type.expected(),
+                    value
+                ));
+            }
+            Some(_) if SUPPORTED_COMPILER_OPTIONS.contains(&key.as_str()) => {
+                supported.insert(key.clone(), value.clone());
+            }
+            Some(_) => {}
+        }
+    }
+    if !errors.is_empty() {
+        bail!("{}", errors.join("\n"));
+    }
+    Ok(Value::Object(supported))
+}
+
+/// Remove the commas following the last element of arrays and objects from
+/// JSON text without comments, which `tsconfig.json` allows.
+fn strip_trailing_commas(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            output.push(c);
+            match c {
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        output.push(escaped);
+                    }
+                }
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => {
+                in_string = true;
+                output.push(c);
+            }
+            ',' => {
+                let rest = chars.clone().find(|c| !c.is_whitespace());
+                if !matches!(rest, Some('}' | ']')) {
+                    output.push(c);
+                }
+            }
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+/// Remove `//` and `/* */` comments from JSON text, which both `deno.json` and
+/// `tsconfig.json` allow.
+fn strip_json_comments(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        if in_string {
+            output.push(c);
+            match c {
+                '\\' => {
+                    if let Some(escaped) = chars.next() {
+                        output.push(escaped);
+                    }
+                }
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match (c, chars.peek()) {
+            ('"', _) => {
+                in_string = true;
+                output.push(c);
+            }
+            ('/', Some('/')) => {
+                for c in chars.by_ref() {
+                    if c == '\n' {
+                        output.push(c);
+                        break;
+                    }
+                }
+            }
+            ('/', Some('*')) => {
+                chars.next();
+                let mut prev = None;
+                for c in chars.by_ref() {
+                    if prev == Some('*') && c == '/' {
+                        break;
+                    }
+                    prev = Some(c);
+                }
+            }
+            _ => output.push(c),
+        }
+    }
+    output
+}
+
+/// A compiler option with an invalid value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TsConfigIssue {
+    /// The value is not of the expected JSON type.
+    InvalidType {
+        key: String,
+        expected: &'static str,
+        found: Value,
+    },
+    /// The value is not one of the values the option accepts.
+    InvalidValue {
+        key: String,
+        found: String,
+        allowed: &'static [&'static str],
+    },
+}
+
+impl fmt::Display for TsConfigIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TsConfigIssue::InvalidType {
+                key,
+                expected,
+                found,
+            } => write!(f, "{}: expected {}, found {}", key, expected, found),
+            TsConfigIssue::InvalidValue {
+                key,
+                found,
+                allowed,
+            } => write!(
+                f,
+                "{}: unsupported value \"{}\", expected one of {}",
+                key,
+                found,
+                allowed
+                    .iter()
+                    .map(|v| format!("\"{}\"", v))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+}
+
+/// The error returned when a `TsConfig` can't be converted into emit options,
+/// listing every invalid compiler option.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TsConfigError {
+    pub issues: Vec<TsConfigIssue>,
 }
 
-use serde::Deserialize;
+impl fmt::Display for TsConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid TypeScript configuration:")?;
+        for issue in &self.issues {
+            write!(f, "\n  {}", issue)?;
+        }
+        Ok(())
+    }
+}
 
-#[derive(Debug, Deserialize)]
-#[serde(rename_all = "camelCase")]
+impl std::error::Error for TsConfigError {}
+
+const IMPORTS_NOT_USED_AS_VALUES: &[&str] = &["remove", "preserve", "error"];
+const JSX: &[&str] = &["react", "react-jsx", "react-jsxdev", "preserve"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EmitConfigOptions {
     pub check_js: bool,
     pub emit_decorator_metadata: bool,
@@ -36,9 +438,133 @@ pub struct EmitConfigOptions {
     pub jsx_import_source: Option<String>,
 }
 
-impl From<TsConfig> for deno_ast::EmitOptions {
-    fn from(config: TsConfig) -> Self {
-        let options: EmitConfigOptions = serde_json::from_value(config.0).unwrap();
+impl Default for EmitConfigOptions {
+    fn default() -> Self {
+        Self {
+            check_js: false,
+            emit_decorator_metadata: false,
+            imports_not_used_as_values: "remove".to_string(),
+            inline_source_map: false,
+            inline_sources: false,
+            source_map: false,
+            jsx: "react".to_string(),
+            jsx_factory: "React.createElement".to_string(),
+            jsx_fragment_factory: "React.Fragment".to_string(),
+            jsx_import_source: None,
+        }
+    }
+}
+
+impl TryFrom<&Value> for EmitConfigOptions {
+    type Error = TsConfigError;
+
+    /// Read the emit options out of `compilerOptions`, defaulting every option
+    /// that is missing or `null`.
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        let defaults = EmitConfigOptions::default();
+        let mut reader = OptionReader {
+            value,
+            issues: Vec::new(),
+        };
+        if !value.is_object() && !value.is_null() {
+            reader.issues.push(TsConfigIssue::InvalidType {
+                key: "compilerOptions".to_string(),
+                expected: "an object",
+                found: value.clone(),
+            });
+        }
+        let options = EmitConfigOptions {
+            check_js: reader.bool("checkJs", defaults.check_js),
+            emit_decorator_metadata: reader
+                .bool("emitDecoratorMetadata", defaults.emit_decorator_metadata),
+            imports_not_used_as_values: reader.one_of(
+                "importsNotUsedAsValues",
+                IMPORTS_NOT_USED_AS_VALUES,
+                defaults.imports_not_used_as_values,
+            ),
+            inline_source_map: reader.bool("inlineSourceMap", defaults.inline_source_map),
+            inline_sources: reader.bool("inlineSources", defaults.inline_sources),
+            source_map: reader.bool("sourceMap", defaults.source_map),
+            jsx: reader.one_of("jsx", JSX, defaults.jsx),
+            jsx_factory: reader.string("jsxFactory").unwrap_or(defaults.jsx_factory),
+            jsx_fragment_factory: reader
+                .string("jsxFragmentFactory")
+                .unwrap_or(defaults.jsx_fragment_factory),
+            jsx_import_source: reader.string("jsxImportSource"),
+        };
+        if reader.issues.is_empty() {
+            Ok(options)
+        } else {
+            Err(TsConfigError {
+                issues: reader.issues,
+            })
+        }
+    }
+}
+
+/// Reads typed compiler options out of a JSON object, recording an issue for
+/// each value of the wrong type instead of stopping at the first one.
+struct OptionReader<'a> {
+    value: &'a Value,
+    issues: Vec<TsConfigIssue>,
+}
+
+impl OptionReader<'_> {
+    fn get(&self, key: &str) -> Option<&Value> {
+        self.value.get(key).filter(|v| !v.is_null())
+    }
+
+    fn invalid_type(&mut self, key: &str, expected: &'static str, found: &Value) {
+        self.issues.push(TsConfigIssue::InvalidType {
+            key: key.to_string(),
+            expected,
+            found: found.clone(),
+        });
+    }
+
+    fn bool(&mut self, key: &str, default: bool) -> bool {
+        match self.get(key).cloned() {
+            Some(Value::Bool(value)) => value,
+            Some(found) => {
+                self.invalid_type(key, "a boolean", &found);
+                default
+            }
+            None => default,
+        }
+    }
+
+    fn string(&mut self, key: &str) -> Option<String> {
+        match self.get(key).cloned() {
+            Some(Value::String(value)) => Some(value),
+            Some(found) => {
+                self.invalid_type(key, "a string", &found);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn one_of(&mut self, key: &str, allowed: &'static [&'static str], default: String) -> String {
+        match self.string(key) {
+            Some(value) if allowed.contains(&value.as_str()) => value,
+            Some(value) => {
+                self.issues.push(TsConfigIssue::InvalidValue {
+                    key: key.to_string(),
+                    found: value,
+                    allowed,
+                });
+                default
+            }
+            None => default,
+        }
+    }
+}
+
+impl TryFrom<TsConfig> for deno_ast::EmitOptions {
+    type Error = TsConfigError;
+
+    fn try_from(config: TsConfig) -> Result<Self, Self::Error> {
+        let options = EmitConfigOptions::try_from(&config.0)?;
         let imports_not_used_as_values = match options.imports_not_used_as_values.as_str() {
             "preserve" => deno_ast::ImportsNotUsedAsValues::Preserve,
             "error" => deno_ast::ImportsNotUsedAsValues::Error,
@@ -50,7 +576,7 @@ impl From<TsConfig> for deno_ast::EmitOptions {
             "react-jsxdev" => (true, true, true),
             _ => (false, false, false),
         };
-        deno_ast::EmitOptions {
+        Ok(deno_ast::EmitOptions {
             emit_metadata: options.emit_decorator_metadata,
             imports_not_used_as_values,
             inline_source_map: options.inline_source_map,
@@ -63,7 +589,7 @@ impl From<TsConfig> for deno_ast::EmitOptions {
             jsx_import_source: options.jsx_import_source,
             transform_jsx,
             var_decl_imports: false,
-        }
+        })
     }
 }
 
@@ -82,7 +608,7 @@ pub fn get_ts_config() -> Result<TsConfig, AnyError> {
 }
 
 /// A function that works like JavaScript's `Object.assign()`.
-fn json_merge(a: &mut Value, b: &Value) {
+pub(crate) fn json_merge(a: &mut Value, b: &Value) {
     match (a, b) {
         (&mut Value::Object(ref mut a), &Value::Object(ref b)) => {
             for (k, v) in b {
@@ -99,6 +625,119 @@ fn json_merge(a: &mut Value, b: &Value) {
 mod tests {
     use super::*;
 
+    #[test]
+    fn empty_ts_config_should_use_defaults() {
+        let options = EmitConfigOptions::try_from(&json!({})).unwrap();
+        assert_eq!(options, EmitConfigOptions::default());
+        let emit_options: deno_ast::EmitOptions =
+            TsConfig::new(json!({ "jsx": null })).try_into().unwrap();
+        assert!(emit_options.transform_jsx);
+    }
+
+    #[test]
+    fn invalid_ts_config_should_list_every_issue() {
+        let err = EmitConfigOptions::try_from(&json!({
+          "sourceMap": "true",
+          "jsx": "solid",
+          "importsNotUsedAsValues": "drop",
+          "jsxFactory": "h"
+        }))
+        .unwrap_err();
+        assert_eq!(
+            err.issues,
+            vec![
+                TsConfigIssue::InvalidValue {
+                    key: "importsNotUsedAsValues".to_string(),
+                    found: "drop".to_string(),
+                    allowed: IMPORTS_NOT_USED_AS_VALUES,
+                },
+                TsConfigIssue::InvalidType {
+                    key: "sourceMap".to_string(),
+                    expected: "a boolean",
+                    found: json!("true"),
+                },
+                TsConfigIssue::InvalidValue {
+                    key: "jsx".to_string(),
+                    found: "solid".to_string(),
+                    allowed: JSX,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strip_json_comments() {
+        let text = r#"{
+          // the jsx runtime
+          "jsx": "react-jsx", /* automatic */
+          "jsxImportSource": "https://esm.sh/preact//"
+        }"#;
+        let value: Value = serde_json::from_str(&strip_json_comments(text)).unwrap();
+        assert_eq!(
+            value,
+            json!({
+              "jsx": "react-jsx",
+              "jsxImportSource": "https://esm.sh/preact//"
+            })
+        );
+    }
+
+    #[test]
+    fn test_strip_trailing_commas() {
+        let text = r#"{
+          "lib": ["dom", "deno.ns",],
+          "jsxFactory": "h, ]",
+        }"#;
+        let value: Value = serde_json::from_str(&strip_trailing_commas(text)).unwrap();
+        assert_eq!(
+            value,
+            json!({
+              "lib": ["dom", "deno.ns"],
+              "jsxFactory": "h, ]"
+            })
+        );
+    }
+
+    #[test]
+    fn test_check_compiler_options() {
+        let options = check_compiler_options(&json!({
+          "jsx": "react-jsx",
+          "jsxImportSource": "preact",
+          "strict": true,
+          "experimentalDecorators": true,
+          "noUncheckedIndexedAccess": true,
+          "exactOptionalPropertyTypes": true,
+          "noImplicitOverride": true,
+          "useUnknownInCatchVariables": false,
+          "strictFunctionTypes": true,
+          "allowUnreachableCode": null,
+          "locale": "en",
+          "diagnostics": true,
+          "generateTrace": "./trace",
+          "lib": ["deno.window", "dom"],
+          "paths": { "@/*": ["./src/*"] }
+        }))
+        .unwrap();
+        assert_eq!(
+            options,
+            json!({
+              "jsx": "react-jsx",
+              "jsxImportSource": "preact"
+            })
+        );
+
+        let err = check_compiler_options(&json!({
+          "sourceMap": "yes",
+          "jsxFactroy": "h",
+          "lib": "dom"
+        }))
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("sourceMap: expected a boolean"));
+        assert!(err.contains("lib: expected an array of strings"));
+        assert!(err.contains("jsxFactroy: unknown compiler option"));
+    }
+
     #[test]
     fn test_json_merge() {
         let mut value_a = json!({
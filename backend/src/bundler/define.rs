@@ -0,0 +1,594 @@
+use deno_ast::swc::{
+    ast::*,
+    common::{util::take::Take, DUMMY_SP},
+    visit::{Visit, VisitMut, VisitMutWith, VisitWith},
+};
+use deno_core::{anyhow::bail, error::AnyError, serde_json::Value};
+use std::collections::{HashMap, HashSet};
+
+/// The global expressions replaced with literal values while transpiling, such
+/// as `process.env.NODE_ENV`, `import.meta.env.MODE` or `__DEV__`.
+///
+/// An expression is left alone in modules declaring a binding with the name of
+/// its root identifier, since it doesn't refer to the global there.
+#[derive(Debug, Clone, Default)]
+pub struct Defines(Vec<Define>);
+
+#[derive(Debug, Clone)]
+struct Define {
+    root: DefineRoot,
+    props: Vec<String>,
+    value: Value,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DefineRoot {
+    Ident(String),
+    ImportMeta,
+}
+
+impl Defines {
+    pub fn new(define: &HashMap<String, Value>) -> Result<Self, AnyError> {
+        let mut defines = Vec::new();
+        for (expr, value) in define {
+            let mut segments = expr.split('.').map(str::trim);
+            let root = match segments.next() {
+                Some("import") => match segments.next() {
+                    Some("meta") => DefineRoot::ImportMeta,
+                    _ => bail!("Invalid define \"{}\".", expr),
+                },
+                Some(ident) if is_ident(ident) => DefineRoot::Ident(ident.to_string()),
+                _ => bail!("Invalid define \"{}\".", expr),
+            };
+            let props: Vec<String> = segments.map(str::to_string).collect();
+            if props.iter().any(|prop| !is_ident(prop)) {
+                bail!("Invalid define \"{}\".", expr);
+            }
+            defines.push(Define {
+                root,
+                props,
+                value: value.clone(),
+            });
+        }
+        // The longest expressions are matched first.
+        defines.sort_by(|a, b| b.props.len().cmp(&a.props.len()));
+        Ok(Self(defines))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Replace the defined expressions of `module`, then remove the branches
+    /// made unreachable by the replaced values.
+    pub fn apply(&self, module: &mut Module) {
+        if self.is_empty() {
+            return;
+        }
+        let mut bindings = BindingCollector::default();
+        module.visit_with(&mut bindings);
+        let defines: Vec<&Define> = self
+            .0
+            .iter()
+            .filter(|define| match &define.root {
+                DefineRoot::Ident(ident) => !bindings.0.contains(ident.as_str()),
+                DefineRoot::ImportMeta => true,
+            })
+            .collect();
+        if defines.is_empty() {
+            return;
+        }
+        module.visit_mut_with(&mut DefineReplacer { defines });
+        module.visit_mut_with(&mut DeadBranchRemover);
+    }
+}
+
+fn is_ident(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_' || c == '$')
+        && chars.all(|c| c.is_alphanumeric() || c == '_' || c == '$')
+}
+
+#[derive(Default)]
+struct BindingCollector(HashSet<String>);
+
+impl Visit for BindingCollector {
+    fn visit_binding_ident(&mut self, n: &BindingIdent) {
+        self.0.insert(n.id.sym.to_string());
+    }
+
+    fn visit_fn_decl(&mut self, n: &FnDecl) {
+        self.0.insert(n.ident.sym.to_string());
+        n.visit_children_with(self);
+    }
+
+    fn visit_class_decl(&mut self, n: &ClassDecl) {
+        self.0.insert(n.ident.sym.to_string());
+        n.visit_children_with(self);
+    }
+
+    fn visit_assign_expr(&mut self, n: &AssignExpr) {
+        // Assignment targets may be parsed as patterns, but don't declare
+        // bindings.
+        n.right.visit_with(self);
+    }
+
+    fn visit_import_specifier(&mut self, n: &ImportSpecifier) {
+        let local = match n {
+            ImportSpecifier::Named(s) => &s.local,
+            ImportSpecifier::Default(s) => &s.local,
+            ImportSpecifier::Namespace(s) => &s.local,
+        };
+        self.0.insert(local.sym.to_string());
+    }
+}
+
+struct DefineReplacer<'a> {
+    defines: Vec<&'a Define>,
+}
+
+impl DefineReplacer<'_> {
+    fn find(&self, expr: &Expr) -> Option<&Value> {
+        self.defines
+            .iter()
+            .find(|define| matches_define(expr, &define.root, &define.props))
+            .map(|define| &define.value)
+    }
+}
+
+fn matches_define(expr: &Expr, root: &DefineRoot, props: &[String]) -> bool {
+    match props.split_last() {
+        Some((prop, rest)) => {
+            let Expr::Member(member) = expr else {
+                return false;
+            };
+            let name = match &member.prop {
+                MemberProp::Ident(ident) => ident.sym.as_ref(),
+                MemberProp::Computed(ComputedPropName { expr, .. }) => match &**expr {
+                    Expr::Lit(Lit::Str(s)) => s.value.as_ref(),
+                    _ => return false,
+                },
+                MemberProp::PrivateName(_) => return false,
+            };
+            name == prop && matches_define(&member.obj, root, rest)
+        }
+        None => match (expr, root) {
+            (Expr::Ident(ident), DefineRoot::Ident(name)) => ident.sym.as_ref() == name,
+            (Expr::MetaProp(meta), DefineRoot::ImportMeta) => meta.kind == MetaPropKind::ImportMeta,
+            (Expr::Paren(paren), _) => matches_define(&paren.expr, root, props),
+            _ => false,
+        },
+    }
+}
+
+impl DefineReplacer<'_> {
+    /// Assignment targets keep referring to the global, but the objects and
+    /// computed keys of their member expressions are replaced.
+    fn visit_mut_target(&mut self, n: &mut Expr) {
+        match n {
+            Expr::Member(member) => {
+                member.obj.visit_mut_with(self);
+                member.prop.visit_mut_with(self);
+            }
+            Expr::Paren(paren) => self.visit_mut_target(&mut paren.expr),
+            Expr::Ident(_) => {}
+            _ => n.visit_mut_children_with(self),
+        }
+    }
+}
+
+impl VisitMut for DefineReplacer<'_> {
+    fn visit_mut_expr(&mut self, n: &mut Expr) {
+        match self.find(n) {
+            Some(value) => *n = value_to_expr(value),
+            None => n.visit_mut_children_with(self),
+        }
+    }
+
+    fn visit_mut_pat_or_expr(&mut self, n: &mut PatOrExpr) {
+        match n {
+            PatOrExpr::Expr(expr) => self.visit_mut_target(expr),
+            PatOrExpr::Pat(pat) => pat.visit_mut_with(self),
+        }
+    }
+
+    fn visit_mut_pat(&mut self, n: &mut Pat) {
+        match n {
+            Pat::Expr(expr) => self.visit_mut_target(expr),
+            _ => n.visit_mut_children_with(self),
+        }
+    }
+
+    fn visit_mut_update_expr(&mut self, n: &mut UpdateExpr) {
+        self.visit_mut_target(&mut n.arg);
+    }
+
+    fn visit_mut_prop(&mut self, n: &mut Prop) {
+        if let Prop::Shorthand(ident) = n {
+            if let Some(value) = self.find(&Expr::Ident(ident.clone())) {
+                *n = Prop::KeyValue(KeyValueProp {
+                    key: PropName::Ident(ident.clone()),
+                    value: Box::new(value_to_expr(value)),
+                });
+            }
+            return;
+        }
+        n.visit_mut_children_with(self);
+    }
+}
+
+/// The literal expression of a JSON value.
+pub(crate) fn value_to_expr(value: &Value) -> Expr {
+    match value {
+        Value::Null => Expr::Lit(Lit::Null(Null { span: DUMMY_SP })),
+        Value::Bool(value) => Expr::Lit(Lit::Bool(Bool {
+            span: DUMMY_SP,
+            value: *value,
+        })),
+        Value::Number(n) => {
+            let value = n.as_f64().unwrap_or_default();
+            let number = Expr::Lit(Lit::Num(Number {
+                span: DUMMY_SP,
+                value: value.abs(),
+                raw: None,
+            }));
+            if value.is_sign_negative() {
+                Expr::Unary(UnaryExpr {
+                    span: DUMMY_SP,
+                    op: UnaryOp::Minus,
+                    arg: Box::new(number),
+                })
+            } else {
+                number
+            }
+        }
+        Value::String(value) => Expr::Lit(Lit::Str(Str {
+            span: DUMMY_SP,
+            value: value.as_str().into(),
+            raw: None,
+        })),
+        Value::Array(values) => Expr::Array(ArrayLit {
+            span: DUMMY_SP,
+            elems: values
+                .iter()
+                .map(|value| {
+                    Some(ExprOrSpread {
+                        spread: None,
+                        expr: Box::new(value_to_expr(value)),
+                    })
+                })
+                .collect(),
+        }),
+        Value::Object(values) => Expr::Object(ObjectLit {
+            span: DUMMY_SP,
+            props: values
+                .iter()
+                .map(|(key, value)| {
+                    PropOrSpread::Prop(Box::new(Prop::KeyValue(KeyValueProp {
+                        key: PropName::Str(Str {
+                            span: DUMMY_SP,
+                            value: key.as_str().into(),
+                            raw: None,
+                        }),
+                        value: Box::new(value_to_expr(value)),
+                    })))
+                })
+                .collect(),
+        }),
+    }
+}
+
+/// Folds conditions on literals and removes the branches they never take.
+struct DeadBranchRemover;
+
+impl VisitMut for DeadBranchRemover {
+    fn visit_mut_expr(&mut self, n: &mut Expr) {
+        n.visit_mut_children_with(self);
+        match n {
+            Expr::Unary(UnaryExpr {
+                op: UnaryOp::Bang,
+                arg,
+                ..
+            }) => {
+                if let Some(value) = truthiness(arg) {
+                    *n = bool_expr(!value);
+                }
+            }
+            Expr::Bin(BinExpr {
+                op, left, right, ..
+            }) => match op {
+                BinaryOp::LogicalAnd | BinaryOp::LogicalOr => {
+                    if let Some(value) = truthiness(left) {
+                        let keep_left = value == (*op == BinaryOp::LogicalOr);
+                        *n = if keep_left {
+                            *left.take()
+                        } else {
+                            *right.take()
+                        };
+                    }
+                }
+                BinaryOp::EqEqEq | BinaryOp::NotEqEq | BinaryOp::EqEq | BinaryOp::NotEq => {
+                    let strict = matches!(op, BinaryOp::EqEqEq | BinaryOp::NotEqEq);
+                    let negate = matches!(op, BinaryOp::NotEqEq | BinaryOp::NotEq);
+                    if let Some(equal) = literals_equal(left, right, strict) {
+                        *n = bool_expr(equal != negate);
+                    }
+                }
+                _ => {}
+            },
+            Expr::Cond(CondExpr {
+                test, cons, alt, ..
+            }) => {
+                if let Some(value) = truthiness(test) {
+                    *n = if value { *cons.take() } else { *alt.take() };
+                }
+            }
+            Expr::Paren(ParenExpr { expr, .. }) if matches!(**expr, Expr::Lit(_)) => {
+                *n = *expr.take();
+            }
+            _ => {}
+        }
+    }
+
+    fn visit_mut_stmt(&mut self, n: &mut Stmt) {
+        n.visit_mut_children_with(self);
+        if let Stmt::If(IfStmt {
+            test, cons, alt, ..
+        }) = n
+        {
+            if let Some(value) = truthiness(test) {
+                let cons = *cons.take();
+                let alt = alt.take().map(|alt| *alt);
+                let (kept, removed) = if value {
+                    (Some(cons), alt)
+                } else {
+                    (alt, Some(cons))
+                };
+                let mut hoisted = HoistedVars::default();
+                if let Some(removed) = &removed {
+                    removed.visit_with(&mut hoisted);
+                }
+                *n = match (kept, hoisted.into_decl()) {
+                    (Some(kept), Some(decl)) => Stmt::Block(BlockStmt {
+                        span: DUMMY_SP,
+                        stmts: vec![kept, decl],
+                    }),
+                    (Some(stmt), None) | (None, Some(stmt)) => stmt,
+                    (None, None) => Stmt::Empty(EmptyStmt { span: DUMMY_SP }),
+                };
+            }
+        }
+    }
+
+    fn visit_mut_stmts(&mut self, n: &mut Vec<Stmt>) {
+        n.visit_mut_children_with(self);
+        n.retain(|stmt| !matches!(stmt, Stmt::Empty(_)));
+    }
+
+    fn visit_mut_module_items(&mut self, n: &mut Vec<ModuleItem>) {
+        n.visit_mut_children_with(self);
+        n.retain(|item| !matches!(item, ModuleItem::Stmt(Stmt::Empty(_))));
+    }
+}
+
+/// The names declared with `var` in a removed branch, which are still hoisted
+/// to the enclosing function.
+#[derive(Default)]
+struct HoistedVars(Vec<Ident>);
+
+impl HoistedVars {
+    /// A declaration of the names without initializer, if there are any.
+    fn into_decl(self) -> Option<Stmt> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let decls = self
+            .0
+            .into_iter()
+            .map(|id| VarDeclarator {
+                span: DUMMY_SP,
+                name: Pat::Ident(BindingIdent { id, type_ann: None }),
+                init: None,
+                definite: false,
+            })
+            .collect();
+        Some(Stmt::Decl(Decl::Var(Box::new(VarDecl {
+            span: DUMMY_SP,
+            kind: VarDeclKind::Var,
+            declare: false,
+            decls,
+        }))))
+    }
+}
+
+impl Visit for HoistedVars {
+    fn visit_var_decl(&mut self, n: &VarDecl) {
+        if n.kind == VarDeclKind::Var {
+            for decl in &n.decls {
+                decl.name.visit_with(self);
+            }
+        }
+    }
+
+    fn visit_binding_ident(&mut self, n: &BindingIdent) {
+        self.0.push(n.id.clone());
+    }
+
+    fn visit_assign_pat_prop(&mut self, n: &AssignPatProp) {
+        self.0.push(n.key.clone());
+    }
+
+    fn visit_catch_clause(&mut self, n: &CatchClause) {
+        n.body.visit_with(self);
+    }
+
+    // Only the names of `var` declarations are collected, and not those of
+    // nested functions, which have their own scope.
+    fn visit_expr(&mut self, _: &Expr) {}
+
+    fn visit_function(&mut self, _: &Function) {}
+
+    fn visit_class(&mut self, _: &Class) {}
+}
+
+fn bool_expr(value: bool) -> Expr {
+    Expr::Lit(Lit::Bool(Bool {
+        span: DUMMY_SP,
+        value,
+    }))
+}
+
+/// The truthiness of a literal, or `None` if `expr` is not a literal.
+fn truthiness(expr: &Expr) -> Option<bool> {
+    match expr {
+        Expr::Lit(Lit::Bool(b)) => Some(b.value),
+        Expr::Lit(Lit::Null(_)) => Some(false),
+        Expr::Lit(Lit::Num(n)) => Some(n.value != 0.0 && !n.value.is_nan()),
+        Expr::Lit(Lit::Str(s)) => Some(!s.value.is_empty()),
+        Expr::Paren(paren) => truthiness(&paren.expr),
+        _ => None,
+    }
+}
+
+/// Compare two literals, or return `None` if either is not a literal or the
+/// loose comparison would need a type conversion.
+fn literals_equal(left: &Expr, right: &Expr, strict: bool) -> Option<bool> {
+    let (Expr::Lit(left), Expr::Lit(right)) = (left, right) else {
+        return None;
+    };
+    match (left, right) {
+        (Lit::Str(l), Lit::Str(r)) => Some(l.value == r.value),
+        (Lit::Num(l), Lit::Num(r)) => Some(l.value == r.value),
+        (Lit::Bool(l), Lit::Bool(r)) => Some(l.value == r.value),
+        (Lit::Null(_), Lit::Null(_)) => Some(true),
+        (Lit::Str(_) | Lit::Num(_) | Lit::Bool(_) | Lit::Null(_), _) if strict => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use deno_ast::swc::{
+        codegen::{text_writer::JsWriter, Emitter},
+        common::{sync::Lrc, FileName, SourceMap},
+        parser::{lexer::Lexer, Parser, StringInput, Syntax},
+    };
+    use deno_core::serde_json::json;
+
+    fn define(entries: &[(&str, Value)]) -> Defines {
+        let map = entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect();
+        Defines::new(&map).unwrap()
+    }
+
+    #[test]
+    fn defines_should_match_member_chains() {
+        let defines = define(&[
+            ("process.env.NODE_ENV", json!("production")),
+            ("import.meta.env.MODE", json!("production")),
+        ]);
+        let expr = Expr::Member(MemberExpr {
+            span: DUMMY_SP,
+            obj: Box::new(Expr::Member(MemberExpr {
+                span: DUMMY_SP,
+                obj: Box::new(Expr::Ident(Ident::new("process".into(), DUMMY_SP))),
+                prop: MemberProp::Ident(Ident::new("env".into(), DUMMY_SP)),
+            })),
+            prop: MemberProp::Ident(Ident::new("NODE_ENV".into(), DUMMY_SP)),
+        });
+        assert!(defines
+            .0
+            .iter()
+            .any(|d| matches_define(&expr, &d.root, &d.props)));
+        assert!(!defines
+            .0
+            .iter()
+            .any(|d| matches_define(&expr, &d.root, &d.props[..1])));
+        assert!(Defines::new(&HashMap::from([("import.env".to_string(), json!(1))])).is_err());
+        assert!(Defines::new(&HashMap::from([("a-b".to_string(), json!(1))])).is_err());
+    }
+
+    #[test]
+    fn dead_branches_should_be_folded() {
+        let lit = |s: &str| {
+            Box::new(Expr::Lit(Lit::Str(Str {
+                span: DUMMY_SP,
+                value: s.into(),
+                raw: None,
+            })))
+        };
+        let mut expr = Expr::Bin(BinExpr {
+            span: DUMMY_SP,
+            op: BinaryOp::NotEqEq,
+            left: lit("production"),
+            right: lit("development"),
+        });
+        expr.visit_mut_with(&mut DeadBranchRemover);
+        assert_eq!(truthiness(&expr), Some(true));
+
+        let mut stmt = Stmt::If(IfStmt {
+            span: DUMMY_SP,
+            test: Box::new(Expr::Unary(UnaryExpr {
+                span: DUMMY_SP,
+                op: UnaryOp::Bang,
+                arg: lit("production"),
+            })),
+            cons: Box::new(Stmt::Debugger(DebuggerStmt { span: DUMMY_SP })),
+            alt: None,
+        });
+        stmt.visit_mut_with(&mut DeadBranchRemover);
+        assert!(matches!(stmt, Stmt::Empty(_)));
+    }
+
+    fn transform(defines: &Defines, source: &str) -> String {
+        let cm: Lrc<SourceMap> = Default::default();
+        let fm = cm.new_source_file(FileName::Anon, source.to_string());
+        let lexer = Lexer::new(
+            Syntax::Es(Default::default()),
+            EsVersion::latest(),
+            StringInput::from(&*fm),
+            None,
+        );
+        let mut module = Parser::new_from(lexer).parse_module().unwrap();
+        defines.apply(&mut module);
+        let mut buf = Vec::new();
+        Emitter {
+            cfg: Default::default(),
+            cm: cm.clone(),
+            comments: None,
+            wr: JsWriter::new(cm, "\n", &mut buf, None),
+        }
+        .emit_module(&module)
+        .unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn defines_should_keep_assignment_targets_and_hoisted_vars() {
+        let defines = define(&[
+            ("__DEV__", json!(false)),
+            ("process.env.NODE_ENV", json!("production")),
+        ]);
+        let code = transform(
+            &defines,
+            r#"
+if (__DEV__) { var debug = true, { level = 1 } = {}; function log() {} }
+__DEV__++;
+__DEV__ = true;
+[__DEV__] = [true];
+export const flags = { __DEV__ };
+process.env[process.env.NODE_ENV] = 1;
+"#,
+        );
+        assert!(code.contains("var debug, level;"), "{}", code);
+        assert!(!code.contains("log"), "{}", code);
+        assert!(code.contains("__DEV__++;"), "{}", code);
+        assert!(code.contains("__DEV__ = true;"), "{}", code);
+        assert!(code.contains("[__DEV__] = ["), "{}", code);
+        assert!(code.contains("__DEV__: false"), "{}", code);
+        assert!(!code.contains("NODE_ENV"), "{}", code);
+    }
+}
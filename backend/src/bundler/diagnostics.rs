@@ -0,0 +1,130 @@
+use serde::Serialize;
+use std::fmt;
+
+/// A syntax error found in a module while bundling.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BundleDiagnostic {
+    pub specifier: String,
+    /// The 1-based line of the error.
+    pub line: usize,
+    /// The 1-based column of the error.
+    pub column: usize,
+    pub message: String,
+    /// The source lines around the error, with the error position marked.
+    pub code_frame: String,
+}
+
+impl From<&deno_ast::Diagnostic> for BundleDiagnostic {
+    fn from(diagnostic: &deno_ast::Diagnostic) -> Self {
+        let position = diagnostic.display_position();
+        let (line, column) = (position.line_number, position.column_number);
+        Self {
+            specifier: diagnostic.specifier.to_string(),
+            line,
+            column,
+            message: diagnostic.message().to_string(),
+            code_frame: code_frame(diagnostic.source.text_str(), line, column),
+        }
+    }
+}
+
+impl fmt::Display for BundleDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "error: {}\n  --> {}:{}:{}\n{}",
+            self.message, self.specifier, self.line, self.column, self.code_frame
+        )
+    }
+}
+
+/// The syntax errors of every module in a bundle.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BundleDiagnostics(pub Vec<BundleDiagnostic>);
+
+impl fmt::Display for BundleDiagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for diagnostic in &self.0 {
+            writeln!(f, "{}", diagnostic)?;
+        }
+        write!(f, "Found {} syntax error(s).", self.0.len())
+    }
+}
+
+impl std::error::Error for BundleDiagnostics {}
+
+/// Render the lines around `line` of `source`, marking `column` with a caret.
+/// Both `line` and `column` are 1-based.
+pub fn code_frame(source: &str, line: usize, column: usize) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let start = line.saturating_sub(3);
+    let end = (line + 1).min(lines.len());
+    let width = end.to_string().len();
+    let mut frame = String::new();
+    for (i, text) in lines.iter().enumerate().take(end).skip(start) {
+        let number = i + 1;
+        if number == line {
+            frame.push_str(&format!("> {:>width$} | {}\n", number, text, width = width));
+            frame.push_str(&format!(
+                "  {:>width$} | {}^\n",
+                "",
+                " ".repeat(column.saturating_sub(1)),
+                width = width
+            ));
+        } else {
+            frame.push_str(&format!("  {:>width$} | {}\n", number, text, width = width));
+        }
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn code_frame_should_mark_the_error() {
+        let source = "import a from \"./a.ts\";\n\nconst b = ;\nconsole.log(a, b);\nexport {};\n";
+        assert_eq!(
+            code_frame(source, 3, 11),
+            [
+                "  1 | import a from \"./a.ts\";",
+                "  2 | ",
+                "> 3 | const b = ;",
+                "    |           ^",
+                "  4 | console.log(a, b);",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn graph_parse_errors_should_have_a_code_frame() {
+        let diagnostic = deno_ast::parse_module(deno_ast::ParseParams {
+            specifier: "file:///app/main.ts".to_string(),
+            text_info: deno_ast::SourceTextInfo::from_string(
+                "const a = 1;\nconst b = ;\n".to_string(),
+            ),
+            media_type: deno_ast::MediaType::TypeScript,
+            capture_tokens: false,
+            scope_analysis: false,
+            maybe_syntax: None,
+        })
+        .unwrap_err();
+        let diagnostic = BundleDiagnostic::from(&diagnostic);
+        assert_eq!(diagnostic.specifier, "file:///app/main.ts");
+        assert_eq!((diagnostic.line, diagnostic.column), (2, 11));
+        assert!(diagnostic.code_frame.contains("> 2 | const b = ;"));
+    }
+
+    #[test]
+    fn code_frame_should_handle_the_first_line() {
+        let source = "const = 1;";
+        assert_eq!(
+            code_frame(source, 1, 7),
+            "> 1 | const = 1;\n    |       ^\n"
+        );
+    }
+}
@@ -0,0 +1,79 @@
+use deno_core::{futures::FutureExt, ModuleSpecifier};
+use deno_graph::source::{LoadFuture, LoadResponse, Loader};
+
+/// Specifier patterns of the modules left out of the bundle, which keeps
+/// importing them instead.
+///
+/// A pattern ending with `:`, `/` or `*` matches every specifier starting with
+/// it (without the `*`), so `node:` matches all Node built-ins. Any other
+/// pattern must match the resolved specifier exactly.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Externals(Vec<String>);
+
+impl Externals {
+    pub fn new(patterns: Vec<String>) -> Self {
+        Self(patterns)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn matches(&self, specifier: &str) -> bool {
+        self.0.iter().any(|pattern| {
+            if let Some(prefix) = pattern.strip_suffix('*') {
+                specifier.starts_with(prefix)
+            } else if pattern.ends_with(':') || pattern.ends_with('/') {
+                specifier.starts_with(pattern.as_str())
+            } else {
+                specifier == pattern
+            }
+        })
+    }
+}
+
+impl Extend<String> for Externals {
+    fn extend<T: IntoIterator<Item = String>>(&mut self, patterns: T) {
+        self.0.extend(patterns);
+    }
+}
+
+/// A graph loader which doesn't load external modules.
+pub struct ExternalLoader<'a> {
+    pub inner: &'a mut dyn Loader,
+    pub externals: &'a Externals,
+}
+
+impl Loader for ExternalLoader<'_> {
+    fn load(&mut self, specifier: &ModuleSpecifier, is_dynamic: bool) -> LoadFuture {
+        if self.externals.matches(specifier.as_str()) {
+            let response = LoadResponse::External {
+                specifier: specifier.clone(),
+            };
+            return async move { Ok(Some(response)) }.boxed_local();
+        }
+        self.inner.load(specifier, is_dynamic)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn externals_should_match_prefixes_and_exact_specifiers() {
+        let externals = Externals::new(vec![
+            "node:".to_string(),
+            "ext:runtime/*".to_string(),
+            "https://deno.land/x/oak@v12.6.0/".to_string(),
+            "https://esm.sh/react@18.2.0".to_string(),
+        ]);
+        assert!(externals.matches("node:buffer"));
+        assert!(externals.matches("ext:runtime/kv.js"));
+        assert!(externals.matches("https://deno.land/x/oak@v12.6.0/mod.ts"));
+        assert!(externals.matches("https://esm.sh/react@18.2.0"));
+        assert!(!externals.matches("https://esm.sh/react@18.2.0/jsx-runtime"));
+        assert!(!externals.matches("ext:other/kv.js"));
+        assert!(!externals.matches("file:///app/node:buffer"));
+    }
+}
@@ -0,0 +1,191 @@
+use deno_ast::MediaType;
+use deno_core::{error::AnyError, ModuleSpecifier};
+use deno_graph::{ModuleError, ModuleGraph, ModuleGraphError, Position, Range, Resolution};
+use std::{collections::BTreeMap, fmt};
+
+use super::diagnostics::{BundleDiagnostic, BundleDiagnostics};
+
+/// A module of the graph that failed to resolve or load.
+#[derive(Debug, Clone)]
+pub struct GraphError {
+    /// The failed specifier, resolved when resolution succeeded and as written
+    /// in the import otherwise.
+    pub specifier: String,
+    /// The module importing the failed specifier, `None` for the roots.
+    pub referrer: Option<ModuleSpecifier>,
+    /// The range of the import in the referrer.
+    pub range: Option<Range>,
+    pub message: String,
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.specifier, self.message)?;
+        match &self.range {
+            Some(range) => write!(
+                f,
+                "\n    at {}:{}:{}",
+                range.specifier,
+                range.start.line + 1,
+                range.start.character + 1
+            ),
+            None => match &self.referrer {
+                Some(referrer) => write!(f, "\n    at {}", referrer),
+                None => write!(f, "\n    (entry point)"),
+            },
+        }
+    }
+}
+
+/// Every error found in a module graph.
+#[derive(Debug, Clone)]
+pub struct GraphErrors(pub Vec<GraphError>);
+
+impl fmt::Display for GraphErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Unable to bundle because {} module(s) could not be resolved or loaded:",
+            self.0.len()
+        )?;
+        for error in &self.0 {
+            write!(f, "\n  {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for GraphErrors {}
+
+/// Check that every root and every imported module of the graph resolved and
+/// loaded, collecting all failures rather than stopping at the first. With
+/// `strict_json_imports`, JSON modules must also be imported with the
+/// `{ type: "json" }` attribute.
+///
+/// Fails with `BundleDiagnostics` when the only failures are syntax errors, and
+/// with `GraphErrors` otherwise.
+pub fn validate_graph(graph: &ModuleGraph, strict_json_imports: bool) -> Result<(), AnyError> {
+    let mut errors = Vec::new();
+    // A module failing to parse is reported once, whatever its importers.
+    let mut syntax_errors = BTreeMap::new();
+    let mut is_syntax_error = |err: &ModuleGraphError| match err {
+        ModuleGraphError::ModuleError(ModuleError::ParseErr(specifier, diagnostic)) => {
+            syntax_errors
+                .entry(specifier.clone())
+                .or_insert_with(|| BundleDiagnostic::from(diagnostic));
+            true
+        }
+        _ => false,
+    };
+    for root in &graph.roots {
+        if let Err(err) = graph.try_get(root) {
+            if is_syntax_error(err) {
+                continue;
+            }
+            errors.push(GraphError {
+                specifier: root.to_string(),
+                referrer: None,
+                range: None,
+                message: err.to_string(),
+            });
+        }
+    }
+    for module in graph.modules() {
+        for (raw_specifier, dep) in &module.dependencies {
+            match &dep.maybe_code {
+                Resolution::Err(err) => errors.push(GraphError {
+                    specifier: raw_specifier.clone(),
+                    referrer: Some(module.specifier.clone()),
+                    range: Some(err.range().clone()),
+                    message: err.to_string(),
+                }),
+                Resolution::Ok(resolved) => match graph.try_get(&resolved.specifier) {
+                    Err(err) if is_syntax_error(err) => {}
+                    Err(err) => errors.push(GraphError {
+                        specifier: resolved.specifier.to_string(),
+                        referrer: Some(module.specifier.clone()),
+                        range: Some(resolved.range.clone()),
+                        message: import_error_message(err),
+                    }),
+                    Ok(Some(imported))
+                        if strict_json_imports
+                            && imported.media_type == MediaType::Json
+                            && dep.maybe_assert_type.as_deref() != Some("json") =>
+                    {
+                        errors.push(GraphError {
+                            specifier: resolved.specifier.to_string(),
+                            referrer: Some(module.specifier.clone()),
+                            range: Some(resolved.range.clone()),
+                            message: "JSON modules must be imported with { type: \"json\" }."
+                                .to_string(),
+                        })
+                    }
+                    Ok(_) => {}
+                },
+                Resolution::None => {}
+            }
+        }
+    }
+    if !errors.is_empty() {
+        errors.extend(syntax_errors.into_iter().map(|(specifier, diagnostic)| {
+            let start = Position {
+                line: diagnostic.line.saturating_sub(1),
+                character: diagnostic.column.saturating_sub(1),
+            };
+            GraphError {
+                specifier: specifier.to_string(),
+                referrer: None,
+                range: Some(Range {
+                    specifier,
+                    start: start.clone(),
+                    end: start,
+                }),
+                message: diagnostic.message,
+            }
+        }));
+        return Err(GraphErrors(errors).into());
+    }
+    if !syntax_errors.is_empty() {
+        return Err(BundleDiagnostics(syntax_errors.into_values().collect()).into());
+    }
+    Ok(())
+}
+
+/// The message of `err`, failing an import. The graph fails imports with a
+/// `type` attribute other than `"json"`, which users may write to import an
+/// asset, so those point at the extension selecting the kind of assets.
+fn import_error_message(err: &ModuleGraphError) -> String {
+    match err {
+        ModuleGraphError::ModuleError(ModuleError::UnsupportedImportAssertionType {
+            kind, ..
+        }) => format!(
+            "The import attribute {{ type: \"{}\" }} is not supported. Assets are \
+             imported without attributes, their kind following the extension of \
+             their specifier.",
+            kind
+        ),
+        _ => err.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unsupported_type_attributes_should_point_at_the_extension() {
+        let specifier = ModuleSpecifier::parse("file:///app/notes.txt").unwrap();
+        let err = ModuleGraphError::ModuleError(ModuleError::UnsupportedImportAssertionType {
+            specifier: specifier.clone(),
+            range: Range {
+                specifier,
+                start: Position::zeroed(),
+                end: Position::zeroed(),
+            },
+            kind: "text".to_string(),
+        });
+        let message = import_error_message(&err);
+        assert!(message.contains("{ type: \"text\" }"));
+        assert!(message.contains("extension"));
+    }
+}
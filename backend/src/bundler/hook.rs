@@ -2,10 +2,88 @@ use deno_ast::swc::{
     bundler::{Hook, ModuleRecord},
     common::Span,
 };
-use deno_core::error::AnyError;
+use deno_core::{error::AnyError, serde_json::Value, ModuleSpecifier};
+use deno_graph::ModuleGraph;
+use import_map::ImportMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use super::define::value_to_expr;
 
 /// This contains the logic for Deno to rewrite the `import.meta` when bundling.
-pub struct BundleHook;
+///
+/// Besides `url` and `main`, the rewritten `import.meta` has a `resolve`
+/// function resolving specifiers against the URL of the original module, and
+/// the extra properties given to `new`.
+///
+/// The specifiers the bundle resolved otherwise, through the import map, npm
+/// or the Node.js built-ins, are resolved by `resolve` like the bundle did:
+/// those imported by the module, and those of the import map. Other
+/// specifiers are resolved as URLs relative to the original module.
+pub struct BundleHook {
+    extra_props: BTreeMap<String, Value>,
+    /// The specifiers `resolve` doesn't resolve as URLs, by module.
+    resolutions: HashMap<String, BTreeMap<String, String>>,
+}
+
+impl BundleHook {
+    pub fn new(extra_props: BTreeMap<String, Value>) -> Self {
+        Self {
+            extra_props,
+            resolutions: HashMap::new(),
+        }
+    }
+
+    /// Resolve the specifiers imported by the modules of `graph` and those of
+    /// `import_map` like the bundle did.
+    pub fn with_resolutions(mut self, graph: &ModuleGraph, import_map: Option<&ImportMap>) -> Self {
+        for module in graph.modules() {
+            let imports = module.dependencies.iter().filter_map(|(specifier, dep)| {
+                dep.get_code()
+                    .map(|resolved| (specifier.as_str(), graph.resolve(resolved)))
+            });
+            let resolutions = resolutions(&module.specifier, imports, import_map);
+            if !resolutions.is_empty() {
+                self.resolutions
+                    .insert(module.specifier.to_string(), resolutions);
+            }
+        }
+        self
+    }
+}
+
+/// The specifiers of `imports` and `import_map` which don't resolve to the URL
+/// they are relative to `referrer`, along with what they resolve to.
+fn resolutions<'a>(
+    referrer: &ModuleSpecifier,
+    imports: impl Iterator<Item = (&'a str, ModuleSpecifier)>,
+    import_map: Option<&ImportMap>,
+) -> BTreeMap<String, String> {
+    let mut resolutions: BTreeMap<String, String> = imports
+        .map(|(specifier, resolved)| (specifier.to_string(), resolved.to_string()))
+        .collect();
+    if let Some(import_map) = import_map {
+        // Only whole specifiers, as the keys of packages ending with a slash
+        // are prefixes of specifiers.
+        let keys: BTreeSet<&str> = std::iter::once(import_map.imports())
+            .chain(import_map.scopes().map(|scope| scope.imports))
+            .flat_map(|imports| imports.entries().map(|entry| entry.raw_key))
+            .filter(|key| !key.ends_with('/'))
+            .collect();
+        for key in keys {
+            if let Ok(resolved) = import_map.resolve(key, referrer) {
+                resolutions
+                    .entry(key.to_string())
+                    .or_insert_with(|| resolved.to_string());
+            }
+        }
+    }
+    resolutions.retain(|specifier, resolved| {
+        referrer
+            .join(specifier)
+            .map_or(true, |url| url.as_str() != resolved.as_str())
+    });
+    resolutions
+}
 
 impl Hook for BundleHook {
     fn get_import_meta_props(
@@ -15,12 +93,13 @@ impl Hook for BundleHook {
     ) -> Result<Vec<deno_ast::swc::ast::KeyValueProp>, AnyError> {
         use deno_ast::swc::ast;
 
-        Ok(vec![
+        let url = module_record.file_name.to_string();
+        let mut props = vec![
             ast::KeyValueProp {
                 key: ast::PropName::Ident(ast::Ident::new("url".into(), span)),
                 value: Box::new(ast::Expr::Lit(ast::Lit::Str(ast::Str {
                     span,
-                    value: module_record.file_name.to_string().into(),
+                    value: url.as_str().into(),
                     raw: None,
                 }))),
             },
@@ -39,6 +118,231 @@ impl Hook for BundleHook {
                     ast::Expr::Lit(ast::Lit::Bool(ast::Bool { span, value: false }))
                 }),
             },
-        ])
+            ast::KeyValueProp {
+                key: ast::PropName::Ident(ast::Ident::new("resolve".into(), span)),
+                value: Box::new(resolve_fn(span, &url, self.resolutions.get(&url))),
+            },
+        ];
+        props.extend(
+            self.extra_props
+                .iter()
+                .map(|(key, value)| ast::KeyValueProp {
+                    key: ast::PropName::Str(ast::Str {
+                        span,
+                        value: key.as_str().into(),
+                        raw: None,
+                    }),
+                    value: Box::new(value_to_expr(value)),
+                }),
+        );
+        Ok(props)
+    }
+}
+
+/// `(specifier) => new URL(specifier, url).href`, or with `resolutions`:
+///
+/// ```js
+/// (specifier) => { __proto__: null, [specifier]: resolved, ... }[specifier] ??
+///   new URL(specifier, url).href
+/// ```
+fn resolve_fn(
+    span: Span,
+    url: &str,
+    resolutions: Option<&BTreeMap<String, String>>,
+) -> deno_ast::swc::ast::Expr {
+    use deno_ast::swc::ast;
+
+    let str_lit = |value: &str| {
+        ast::Expr::Lit(ast::Lit::Str(ast::Str {
+            span,
+            value: value.into(),
+            raw: None,
+        }))
+    };
+    let specifier = ast::Ident::new("specifier".into(), span);
+    let new_url = ast::Expr::New(ast::NewExpr {
+        span,
+        callee: Box::new(ast::Expr::Ident(ast::Ident::new("URL".into(), span))),
+        args: Some(vec![
+            ast::ExprOrSpread {
+                spread: None,
+                expr: Box::new(ast::Expr::Ident(specifier.clone())),
+            },
+            ast::ExprOrSpread {
+                spread: None,
+                expr: Box::new(str_lit(url)),
+            },
+        ]),
+        type_args: None,
+    });
+    let href = ast::Expr::Member(ast::MemberExpr {
+        span,
+        obj: Box::new(new_url),
+        prop: ast::MemberProp::Ident(ast::Ident::new("href".into(), span)),
+    });
+    let body = match resolutions.filter(|resolutions| !resolutions.is_empty()) {
+        None => href,
+        Some(resolutions) => {
+            // The prototype is null, so that `constructor` and the like
+            // aren't found.
+            let proto = ast::PropOrSpread::Prop(Box::new(ast::Prop::KeyValue(ast::KeyValueProp {
+                key: ast::PropName::Ident(ast::Ident::new("__proto__".into(), span)),
+                value: Box::new(ast::Expr::Lit(ast::Lit::Null(ast::Null { span }))),
+            })));
+            let entries = resolutions.iter().map(|(specifier, resolved)| {
+                ast::PropOrSpread::Prop(Box::new(ast::Prop::KeyValue(ast::KeyValueProp {
+                    key: ast::PropName::Computed(ast::ComputedPropName {
+                        span,
+                        expr: Box::new(str_lit(specifier)),
+                    }),
+                    value: Box::new(str_lit(resolved)),
+                })))
+            });
+            let table = ast::Expr::Paren(ast::ParenExpr {
+                span,
+                expr: Box::new(ast::Expr::Object(ast::ObjectLit {
+                    span,
+                    props: std::iter::once(proto).chain(entries).collect(),
+                })),
+            });
+            ast::Expr::Bin(ast::BinExpr {
+                span,
+                op: ast::BinaryOp::NullishCoalescing,
+                left: Box::new(ast::Expr::Member(ast::MemberExpr {
+                    span,
+                    obj: Box::new(table),
+                    prop: ast::MemberProp::Computed(ast::ComputedPropName {
+                        span,
+                        expr: Box::new(ast::Expr::Ident(specifier.clone())),
+                    }),
+                })),
+                right: Box::new(href),
+            })
+        }
+    };
+    ast::Expr::Arrow(ast::ArrowExpr {
+        span,
+        params: vec![ast::Pat::Ident(ast::BindingIdent {
+            id: specifier,
+            type_ann: None,
+        })],
+        body: Box::new(ast::BlockStmtOrExpr::Expr(Box::new(body))),
+        is_async: false,
+        is_generator: false,
+        type_params: None,
+        return_type: None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use deno_ast::swc::{
+        ast,
+        common::{FileName, DUMMY_SP},
+        visit::{Visit, VisitWith},
+    };
+    use deno_core::serde_json::json;
+
+    #[derive(Default)]
+    struct Strings(Vec<String>);
+
+    impl Visit for Strings {
+        fn visit_str(&mut self, n: &ast::Str) {
+            self.0.push(n.value.to_string());
+        }
+    }
+
+    fn props(hook: &BundleHook, url: &str, is_entry: bool) -> BTreeMap<String, ast::Expr> {
+        let record = ModuleRecord {
+            file_name: FileName::Url(ModuleSpecifier::parse(url).unwrap()),
+            is_entry,
+        };
+        hook.get_import_meta_props(DUMMY_SP, &record)
+            .unwrap()
+            .into_iter()
+            .map(|prop| {
+                let key = match prop.key {
+                    ast::PropName::Ident(ident) => ident.sym.to_string(),
+                    ast::PropName::Str(s) => s.value.to_string(),
+                    _ => panic!("unexpected key"),
+                };
+                (key, *prop.value)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn import_meta_should_be_rewritten() {
+        let hook = BundleHook::new(BTreeMap::from([
+            ("deploymentId".to_string(), json!("abc123")),
+            ("buildTime".to_string(), json!(1700000000)),
+        ]));
+        let entry = props(&hook, "file:///app/main.ts", true);
+        assert_eq!(
+            entry.keys().map(String::as_str).collect::<Vec<_>>(),
+            ["buildTime", "deploymentId", "main", "resolve", "url"]
+        );
+        assert!(
+            matches!(&entry["url"], ast::Expr::Lit(ast::Lit::Str(s)) if &*s.value == "file:///app/main.ts")
+        );
+        assert!(matches!(&entry["main"], ast::Expr::Member(_)));
+        assert!(
+            matches!(&entry["deploymentId"], ast::Expr::Lit(ast::Lit::Str(s)) if &*s.value == "abc123")
+        );
+
+        let module = props(&hook, "file:///app/util.ts", false);
+        assert!(matches!(&module["main"], ast::Expr::Lit(ast::Lit::Bool(b)) if !b.value));
+        let ast::Expr::Arrow(resolve) = &module["resolve"] else {
+            panic!("resolve should be a function");
+        };
+        let mut strings = Strings::default();
+        resolve.visit_with(&mut strings);
+        assert_eq!(strings.0, ["file:///app/util.ts"]);
+    }
+
+    #[test]
+    fn import_meta_resolve_should_resolve_like_the_bundle() {
+        let referrer = ModuleSpecifier::parse("file:///app/main.ts").unwrap();
+        let import_map = import_map::parse_from_json(
+            &ModuleSpecifier::parse("file:///app/import_map.json").unwrap(),
+            r#"{ "imports": { "preact": "https://esm.sh/preact@10.16.0", "std/": "https://deno.land/std/" } }"#,
+        )
+        .unwrap()
+        .import_map;
+        let imports = [
+            (
+                "./util.ts",
+                ModuleSpecifier::parse("file:///app/util.ts").unwrap(),
+            ),
+            (
+                "npm:zod",
+                ModuleSpecifier::parse("npm:/zod@3.22.0/lib/index.mjs").unwrap(),
+            ),
+        ];
+        let resolved = resolutions(&referrer, imports.into_iter(), Some(&import_map));
+        assert_eq!(
+            resolved,
+            BTreeMap::from([
+                (
+                    "npm:zod".to_string(),
+                    "npm:/zod@3.22.0/lib/index.mjs".to_string()
+                ),
+                (
+                    "preact".to_string(),
+                    "https://esm.sh/preact@10.16.0".to_string()
+                ),
+            ])
+        );
+
+        let mut hook = BundleHook::new(BTreeMap::new());
+        hook.resolutions.insert(referrer.to_string(), resolved);
+        let props = props(&hook, referrer.as_str(), true);
+        let mut strings = Strings::default();
+        props["resolve"].visit_with(&mut strings);
+        assert!(strings
+            .0
+            .contains(&"https://esm.sh/preact@10.16.0".to_string()));
+        assert!(strings.0.contains(&"file:///app/main.ts".to_string()));
     }
 }
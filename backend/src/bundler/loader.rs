@@ -5,33 +5,161 @@ use deno_ast::{
         common::{comments::SingleThreadedComments, FileName, Mark, SourceMap, Spanned},
         parser::{error::Error as SwcError, lexer::Lexer, StringInput},
     },
-    Diagnostic, MediaType, SourceRangedForSpanned,
+    Diagnostic, MediaType, SourceRangedForSpanned, SourceTextInfo,
 };
 
-use deno_core::{anyhow::anyhow, error::AnyError, ModuleSpecifier};
+use deno_core::{anyhow::anyhow, error::AnyError, serde_json, ModuleSpecifier};
 use deno_graph::ModuleGraph;
-use std::rc::Rc;
+use serde::de::IgnoredAny;
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap, HashSet},
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+use swc::visit::{VisitMut, VisitMutWith};
+
+use super::{
+    define::Defines,
+    diagnostics::{code_frame, BundleDiagnostic, BundleDiagnostics},
+};
+
+type TranspiledModule = (Rc<swc::common::SourceFile>, swc::ast::Module);
+
+/// Transpiled modules kept between bundles of a session. An entry is reused
+/// only while the source of its module is unchanged, so the options of a
+/// session must not change between bundles.
+#[derive(Default)]
+pub struct TranspileCache(RefCell<HashMap<ModuleSpecifier, (u64, TranspiledModule)>>);
+
+impl TranspileCache {
+    fn get(&self, specifier: &ModuleSpecifier, source: &str) -> Option<TranspiledModule> {
+        match self.0.borrow().get(specifier) {
+            Some((hash, transpiled)) if *hash == hash_source(source) => Some(transpiled.clone()),
+            _ => None,
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    fn insert(&self, specifier: ModuleSpecifier, source: &str, transpiled: TranspiledModule) {
+        self.0
+            .borrow_mut()
+            .insert(specifier, (hash_source(source), transpiled));
+    }
+}
+
+fn hash_source(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
 
 /// A module loader for swc which does the appropriate retrieval and transpiling
 /// of modules from the graph.
 pub struct BundleLoader<'a> {
     cm: Rc<swc::common::SourceMap>,
     emit_options: &'a deno_ast::EmitOptions,
+    defines: &'a Defines,
     graph: &'a ModuleGraph,
+    rewrites: &'a HashMap<ModuleSpecifier, String>,
+    cache: &'a TranspileCache,
+    preloaded: RefCell<HashMap<ModuleSpecifier, TranspiledModule>>,
 }
 
 impl<'a> BundleLoader<'a> {
     pub fn new(
         cm: Rc<swc::common::SourceMap>,
         emit_options: &'a deno_ast::EmitOptions,
+        defines: &'a Defines,
         graph: &'a ModuleGraph,
+        rewrites: &'a HashMap<ModuleSpecifier, String>,
+        cache: &'a TranspileCache,
     ) -> Self {
         Self {
             cm,
             emit_options,
+            defines,
             graph,
+            rewrites,
+            cache,
+            preloaded: Default::default(),
         }
     }
+
+    /// Transpile every module bundled from `entry` up front, so the syntax
+    /// errors of all modules are reported together instead of stopping at the
+    /// first module that fails. Returns the modules that will be bundled.
+    pub fn preload(&self, entry: &ModuleSpecifier) -> Result<Vec<ModuleSpecifier>, AnyError> {
+        let mut diagnostics = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![entry.clone()];
+        while let Some(specifier) = stack.pop() {
+            if !seen.insert(specifier.clone()) || self.rewrites.contains_key(&specifier) {
+                continue;
+            }
+            let Some(m) = self.graph.get(&specifier) else {
+                continue;
+            };
+            match self.transpile(&specifier) {
+                Ok(transpiled) => {
+                    self.preloaded.borrow_mut().insert(specifier, transpiled);
+                }
+                Err(err) => match err.downcast::<BundleDiagnostics>() {
+                    Ok(module_diagnostics) => diagnostics.extend(module_diagnostics.0),
+                    Err(err) => return Err(err),
+                },
+            }
+            for dep in m.dependencies.values() {
+                if let Some(dep) = dep.get_code() {
+                    stack.push(self.graph.resolve(dep));
+                }
+            }
+        }
+        if diagnostics.is_empty() {
+            let mut modules: Vec<_> = self.preloaded.borrow().keys().cloned().collect();
+            modules.sort();
+            Ok(modules)
+        } else {
+            Err(BundleDiagnostics(diagnostics).into())
+        }
+    }
+
+    fn transpile(&self, specifier: &ModuleSpecifier) -> Result<TranspiledModule, AnyError> {
+        let Some(m) = self.graph.get(specifier) else {
+            return Err(anyhow!(
+                "Module \"{}\" unexpectedly missing when bundling.",
+                specifier
+            ));
+        };
+        let source = m.maybe_source.as_ref().map(|s| s.as_ref()).unwrap_or("");
+        let (fm, mut module) = match self.cache.get(specifier, source) {
+            Some(transpiled) => transpiled,
+            None => {
+                let transpiled = transpile_module(
+                    specifier,
+                    source,
+                    m.media_type,
+                    self.emit_options,
+                    self.defines,
+                    self.cm.clone(),
+                )?;
+                self.cache
+                    .insert(specifier.clone(), source, transpiled.clone());
+                transpiled
+            }
+        };
+        if !self.rewrites.is_empty() {
+            module.visit_mut_with(&mut SpecifierRewriter {
+                referrer: specifier,
+                graph: self.graph,
+                rewrites: self.rewrites,
+            });
+        }
+        Ok((fm, module))
+    }
 }
 
 impl swc::bundler::Load for BundleLoader<'_> {
@@ -41,25 +169,16 @@ impl swc::bundler::Load for BundleLoader<'_> {
     ) -> Result<swc::bundler::ModuleData, AnyError> {
         match file_name {
             swc::common::FileName::Url(specifier) => {
-                if let Some(m) = self.graph.get(specifier) {
-                    let (fm, module) = transpile_module(
-                        specifier,
-                        m.maybe_source.as_ref().map(|s| s.as_ref()).unwrap_or(""),
-                        m.media_type,
-                        self.emit_options,
-                        self.cm.clone(),
-                    )?;
-                    Ok(swc::bundler::ModuleData {
-                        fm,
-                        module,
-                        helpers: Default::default(),
-                    })
-                } else {
-                    Err(anyhow!(
-                        "Module \"{}\" unexpectedly missing when bundling.",
-                        specifier
-                    ))
-                }
+                let preloaded = self.preloaded.borrow_mut().remove(specifier);
+                let (fm, module) = match preloaded {
+                    Some(transpiled) => transpiled,
+                    None => self.transpile(specifier)?,
+                };
+                Ok(swc::bundler::ModuleData {
+                    fm,
+                    module,
+                    helpers: Default::default(),
+                })
             }
             _ => unreachable!(
                 "Received a request for unsupported filename {:?}",
@@ -69,41 +188,90 @@ impl swc::bundler::Load for BundleLoader<'_> {
     }
 }
 
+/// Rewrites the specifiers of imports, re-exports and dynamic imports which
+/// resolve to one of `rewrites`, so the bundler leaves them as external imports.
+struct SpecifierRewriter<'a> {
+    referrer: &'a ModuleSpecifier,
+    graph: &'a ModuleGraph,
+    rewrites: &'a HashMap<ModuleSpecifier, String>,
+}
+
+impl SpecifierRewriter<'_> {
+    fn rewrite(&self, src: &mut swc::ast::Str) {
+        if let Some(specifier) = self
+            .graph
+            .resolve_dependency(&src.value, self.referrer, false)
+        {
+            if let Some(rewrite) = self.rewrites.get(&self.graph.resolve(specifier)) {
+                src.value = rewrite.as_str().into();
+                src.raw = None;
+            }
+        }
+    }
+}
+
+impl VisitMut for SpecifierRewriter<'_> {
+    fn visit_mut_import_decl(&mut self, n: &mut swc::ast::ImportDecl) {
+        self.rewrite(&mut n.src);
+    }
+
+    fn visit_mut_export_all(&mut self, n: &mut swc::ast::ExportAll) {
+        self.rewrite(&mut n.src);
+    }
+
+    fn visit_mut_named_export(&mut self, n: &mut swc::ast::NamedExport) {
+        if let Some(src) = n.src.as_mut() {
+            self.rewrite(src);
+        }
+    }
+
+    fn visit_mut_call_expr(&mut self, n: &mut swc::ast::CallExpr) {
+        n.visit_mut_children_with(self);
+        if let swc::ast::Callee::Import(_) = n.callee {
+            if let Some(arg) = n.args.first_mut() {
+                if let swc::ast::Expr::Lit(swc::ast::Lit::Str(src)) = &mut *arg.expr {
+                    self.rewrite(src);
+                }
+            }
+        }
+    }
+}
+
 /// Transpiles a source module into an swc SourceFile.
 fn transpile_module(
     specifier: &ModuleSpecifier,
     source: &str,
     media_type: MediaType,
     options: &deno_ast::EmitOptions,
+    defines: &Defines,
     cm: Rc<swc::common::SourceMap>,
 ) -> Result<(Rc<swc::common::SourceFile>, swc::ast::Module), AnyError> {
     let source = strip_bom(source);
-    let source = if media_type == MediaType::Json {
-        format!(
-            "export default JSON.parse(`{}`);",
-            source.replace("${", "\\${").replace('`', "\\`")
-        )
-    } else {
-        source.to_string()
-    };
-    let source_file = cm.new_source_file(FileName::Url(specifier.clone()), source);
+    let source_file = cm.new_source_file(FileName::Url(specifier.clone()), source.to_string());
+    if media_type == MediaType::Json {
+        return json_module(specifier, source_file, &cm);
+    }
     let input = StringInput::from(&*source_file);
     let comments = SingleThreadedComments::default();
-    let syntax = if media_type == MediaType::Json {
-        get_syntax(MediaType::JavaScript)
-    } else {
-        get_syntax(media_type)
-    };
+    let syntax = get_syntax(media_type);
     let lexer = Lexer::new(syntax, deno_ast::ES_VERSION, input, Some(&comments));
     let mut parser = swc::parser::Parser::new_from(lexer);
-    let module = parser
-        .parse_module()
-        .map_err(|e| swc_err_to_diagnostic(&cm, specifier, e))?;
-    let diagnostics = parser
+    let result = parser.parse_module();
+    // Recoverable errors are only fatal when deno_ast considers them so, the
+    // way they are when the module graph is built.
+    let (mut bundle_diagnostics, diagnostics): (Vec<_>, Vec<_>) = parser
         .take_errors()
         .into_iter()
-        .map(|e| swc_err_to_diagnostic(&cm, specifier, e))
-        .collect::<Vec<_>>();
+        .map(|e| swc_err_to_diagnostics(&cm, &source_file, specifier, e))
+        .unzip();
+    let module = match result {
+        Ok(module) => module,
+        Err(e) => {
+            let (diagnostic, _) = swc_err_to_diagnostics(&cm, &source_file, specifier, e);
+            bundle_diagnostics.push(diagnostic);
+            return Err(BundleDiagnostics(bundle_diagnostics).into());
+        }
+    };
 
     let top_level_mark = Mark::fresh(Mark::root());
     let program = deno_ast::fold_program(
@@ -113,15 +281,86 @@ fn transpile_module(
         &comments,
         top_level_mark,
         &diagnostics,
-    )?;
-    let module = match program {
+    )
+    .map_err(|err| {
+        if bundle_diagnostics.is_empty() {
+            err
+        } else {
+            BundleDiagnostics(bundle_diagnostics).into()
+        }
+    })?;
+    let mut module = match program {
         swc::ast::Program::Module(module) => module,
         _ => unreachable!(),
     };
+    defines.apply(&mut module);
+
+    Ok((source_file, module))
+}
 
+/// Turns a JSON module into a module default exporting its value as a literal
+/// expression, so the value isn't parsed again at runtime.
+fn json_module(
+    specifier: &ModuleSpecifier,
+    source_file: Rc<swc::common::SourceFile>,
+    cm: &SourceMap,
+) -> Result<TranspiledModule, AnyError> {
+    // Every JSON document is a valid expression, but not the other way around.
+    if let Err(err) = serde_json::from_str::<IgnoredAny>(&source_file.src) {
+        let (line, column) = (err.line(), err.column());
+        return Err(BundleDiagnostics(vec![BundleDiagnostic {
+            specifier: specifier.to_string(),
+            line,
+            column,
+            message: format!("Invalid JSON: {}", err),
+            code_frame: code_frame(&source_file.src, line, column),
+        }])
+        .into());
+    }
+    let input = StringInput::from(&*source_file);
+    let lexer = Lexer::new(
+        get_syntax(MediaType::JavaScript),
+        deno_ast::ES_VERSION,
+        input,
+        None,
+    );
+    let mut parser = swc::parser::Parser::new_from(lexer);
+    let mut expr = parser.parse_expr().map_err(|e| {
+        let (diagnostic, _) = swc_err_to_diagnostics(cm, &source_file, specifier, e);
+        BundleDiagnostics(vec![diagnostic])
+    })?;
+    expr.visit_mut_with(&mut ProtoKeys);
+    let span = expr.span();
+    let module = swc::ast::Module {
+        span,
+        body: vec![swc::ast::ModuleItem::ModuleDecl(
+            swc::ast::ModuleDecl::ExportDefaultExpr(swc::ast::ExportDefaultExpr { span, expr }),
+        )],
+        shebang: None,
+    };
     Ok((source_file, module))
 }
 
+/// Turns the `"__proto__"` keys of a JSON document into computed keys, since
+/// in an object literal they set the prototype instead of an own property.
+struct ProtoKeys;
+
+impl VisitMut for ProtoKeys {
+    fn visit_mut_prop_name(&mut self, n: &mut swc::ast::PropName) {
+        if matches!(n, swc::ast::PropName::Str(key) if &*key.value == "__proto__") {
+            let span = n.span();
+            *n = swc::ast::PropName::Computed(swc::ast::ComputedPropName {
+                span,
+                expr: Box::new(swc::ast::Expr::Lit(swc::ast::Lit::Str(swc::ast::Str {
+                    span,
+                    value: "__proto__".into(),
+                    raw: None,
+                }))),
+            });
+        }
+    }
+}
+
 const BOM_CHAR: char = '\u{FEFF}';
 /// Strips the byte order mark from the provided text if it exists.
 fn strip_bom(text: &str) -> &str {
@@ -132,20 +371,60 @@ fn strip_bom(text: &str) -> &str {
     }
 }
 
-fn swc_err_to_diagnostic(
+/// Turn a parse error into the diagnostic reported to users, and the one
+/// `deno_ast::fold_program` filters fatal errors from.
+fn swc_err_to_diagnostics(
     source_map: &SourceMap,
+    source_file: &swc::common::SourceFile,
     specifier: &ModuleSpecifier,
     err: SwcError,
-) -> Diagnostic {
+) -> (BundleDiagnostic, Diagnostic) {
     let location = source_map.lookup_char_pos(err.span().lo);
-    Diagnostic {
+    let column = location.col_display + 1;
+    let bundle_diagnostic = BundleDiagnostic {
+        specifier: specifier.to_string(),
+        line: location.line,
+        column,
+        message: err.kind().msg().to_string(),
+        code_frame: code_frame(&location.file.src, location.line, column),
+    };
+    let diagnostic = Diagnostic {
         specifier: specifier.to_string(),
         range: err.range(),
-        // display_position: LineAndColumnDisplay {
-        //     line_number: location.line,
-        //     column_number: location.col_display + 1,
-        // },
         kind: err.into_kind(),
-        source: todo!(),
+        source: SourceTextInfo::from_string(source_file.src.to_string()),
+    };
+    (bundle_diagnostic, diagnostic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use swc::visit::{Visit, VisitWith};
+
+    #[derive(Default)]
+    struct PropNames(Vec<swc::ast::PropName>);
+
+    impl Visit for PropNames {
+        fn visit_prop_name(&mut self, n: &swc::ast::PropName) {
+            self.0.push(n.clone());
+        }
+    }
+
+    #[test]
+    fn json_module_should_keep_proto_keys_as_own_properties() {
+        let cm = Rc::new(SourceMap::default());
+        let specifier = ModuleSpecifier::parse("file:///app/data.json").unwrap();
+        let source = r#"{ "__proto__": { "admin": true }, "nested": [{ "__proto__": null }] }"#;
+        let source_file = cm.new_source_file(FileName::Url(specifier.clone()), source.into());
+        let (_, module) = json_module(&specifier, source_file, &cm).unwrap();
+        let mut names = PropNames::default();
+        module.visit_with(&mut names);
+        let computed = names
+            .0
+            .iter()
+            .filter(|name| matches!(name, swc::ast::PropName::Computed(_)))
+            .count();
+        assert_eq!((names.0.len(), computed), (4, 2));
     }
 }
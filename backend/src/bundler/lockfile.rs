@@ -0,0 +1,204 @@
+use deno_core::{
+    anyhow::{bail, Context},
+    error::AnyError,
+    serde_json,
+};
+use deno_graph::ModuleGraph;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, fmt, path::Path};
+
+use super::write_file;
+
+const LOCKFILE_VERSION: &str = "2";
+
+/// The content of a lockfile, in the shape of `deno.lock`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockfile {
+    pub version: String,
+    /// The hex encoded SHA-256 of the source of every remote module.
+    #[serde(default)]
+    pub remote: BTreeMap<String, String>,
+}
+
+impl Default for Lockfile {
+    fn default() -> Self {
+        Self {
+            version: LOCKFILE_VERSION.to_string(),
+            remote: BTreeMap::new(),
+        }
+    }
+}
+
+/// A remote module whose source doesn't match the lockfile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntegrityMismatch {
+    pub specifier: String,
+    pub expected: String,
+    pub actual: String,
+}
+
+/// Every remote module whose source doesn't match the lockfile.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockfileError(pub Vec<IntegrityMismatch>);
+
+impl fmt::Display for LockfileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The source of {} remote module(s) doesn't match the lockfile:",
+            self.0.len()
+        )?;
+        for mismatch in &self.0 {
+            write!(
+                f,
+                "\n  {}\n    expected: {}\n    actual:   {}",
+                mismatch.specifier, mismatch.expected, mismatch.actual
+            )?;
+        }
+        write!(f, "\nUpdate the lockfile if the changes are expected.")
+    }
+}
+
+impl std::error::Error for LockfileError {}
+
+impl Lockfile {
+    /// Check the hashes of `remote` against the lockfile, adding the modules it
+    /// doesn't know yet. With `update`, the lockfile is replaced by `remote`
+    /// instead. Returns whether the lockfile changed.
+    pub fn check(
+        &mut self,
+        remote: BTreeMap<String, String>,
+        update: bool,
+    ) -> Result<bool, LockfileError> {
+        if update {
+            let changed = self.remote != remote;
+            self.remote = remote;
+            return Ok(changed);
+        }
+        let mut mismatches = Vec::new();
+        let mut changed = false;
+        for (specifier, actual) in remote {
+            match self.remote.get(&specifier) {
+                Some(expected) if *expected != actual => mismatches.push(IntegrityMismatch {
+                    specifier,
+                    expected: expected.clone(),
+                    actual,
+                }),
+                Some(_) => {}
+                None => {
+                    self.remote.insert(specifier, actual);
+                    changed = true;
+                }
+            }
+        }
+        if mismatches.is_empty() {
+            Ok(changed)
+        } else {
+            Err(LockfileError(mismatches))
+        }
+    }
+}
+
+/// Verify the remote modules of `graph` against the lockfile at `path`, which
+/// is written when it doesn't exist yet, when new remote modules were added to
+/// the graph, or with `update`.
+pub fn check_lockfile(graph: &ModuleGraph, path: &Path, update: bool) -> Result<(), AnyError> {
+    let (mut lockfile, exists) = match std::fs::read_to_string(path) {
+        Ok(json) => {
+            let lockfile: Lockfile = serde_json::from_str(&json)
+                .with_context(|| format!("Invalid lockfile {}", path.display()))?;
+            if lockfile.version != LOCKFILE_VERSION && !update {
+                bail!(
+                    "Unsupported lockfile version \"{}\" in {}.",
+                    lockfile.version,
+                    path.display()
+                );
+            }
+            (lockfile, true)
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => (Lockfile::default(), false),
+        Err(err) => return Err(err).with_context(|| format!("Unable to read {}", path.display())),
+    };
+    lockfile.version = LOCKFILE_VERSION.to_string();
+    let changed = lockfile.check(remote_hashes(graph), update)?;
+    if changed || !exists {
+        let json = serde_json::to_string_pretty(&lockfile)?;
+        write_file(path, format!("{}\n", json).as_bytes(), 0o644)?;
+    }
+    Ok(())
+}
+
+/// The hex encoded SHA-256 of the source of every remote module of `graph`.
+fn remote_hashes(graph: &ModuleGraph) -> BTreeMap<String, String> {
+    graph
+        .modules()
+        .filter(|module| matches!(module.specifier.scheme(), "http" | "https"))
+        .filter_map(|module| {
+            let source = module.maybe_source.as_ref()?;
+            let hash = format!("{:x}", Sha256::digest(source.as_bytes()));
+            Some((module.specifier.to_string(), hash))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn remote(entries: &[(&str, &str)]) -> BTreeMap<String, String> {
+        entries
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn lockfile_should_add_new_modules_and_reject_changed_ones() {
+        let mut lockfile = Lockfile::default();
+        let changed = lockfile
+            .check(remote(&[("https://deno.land/x/a.ts", "aaa")]), false)
+            .unwrap();
+        assert!(changed);
+        let changed = lockfile
+            .check(remote(&[("https://deno.land/x/a.ts", "aaa")]), false)
+            .unwrap();
+        assert!(!changed);
+
+        let err = lockfile
+            .check(remote(&[("https://deno.land/x/a.ts", "bbb")]), false)
+            .unwrap_err();
+        assert_eq!(
+            err.0,
+            vec![IntegrityMismatch {
+                specifier: "https://deno.land/x/a.ts".to_string(),
+                expected: "aaa".to_string(),
+                actual: "bbb".to_string(),
+            }]
+        );
+
+        let changed = lockfile
+            .check(remote(&[("https://deno.land/x/a.ts", "bbb")]), true)
+            .unwrap();
+        assert!(changed);
+        assert_eq!(
+            lockfile.remote,
+            remote(&[("https://deno.land/x/a.ts", "bbb")])
+        );
+    }
+
+    #[test]
+    fn lockfile_should_have_the_deno_lock_shape() {
+        let lockfile = Lockfile {
+            version: LOCKFILE_VERSION.to_string(),
+            remote: remote(&[("https://deno.land/x/a.ts", "aaa")]),
+        };
+        assert_eq!(
+            serde_json::to_value(&lockfile).unwrap(),
+            serde_json::json!({
+              "version": "2",
+              "remote": { "https://deno.land/x/a.ts": "aaa" }
+            })
+        );
+    }
+}
@@ -0,0 +1,528 @@
+use deno_ast::swc::{
+    ast::*,
+    common::{util::take::Take, DUMMY_SP},
+    visit::{VisitMut, VisitMutWith},
+};
+use std::collections::HashMap;
+
+/// Turn a bundled ES module into statements that can be the body of the async
+/// `mainModule` function of `layout.j2`, where `import` and `export` are not
+/// allowed.
+///
+/// Imports become dynamic imports awaited before the rest of the body, and the
+/// exports are returned as a namespace object, whose getters keep the bindings
+/// live like those of a module namespace. The imported bindings are read from
+/// the namespaces of the dynamic imports, so they stay live as well.
+pub(crate) fn to_function_body(mut module: Module) -> Module {
+    let mut imports = Vec::new();
+    let mut body = Vec::new();
+    let mut exports = Vec::new();
+    let mut star_exports = Vec::new();
+    // The namespace members read in place of the imported bindings.
+    let mut imported = HashMap::new();
+
+    let (import_decls, items): (Vec<_>, Vec<_>) = module
+        .body
+        .take()
+        .into_iter()
+        .partition(|item| matches!(item, ModuleItem::ModuleDecl(ModuleDecl::Import(_))));
+    for item in import_decls {
+        let ModuleItem::ModuleDecl(ModuleDecl::Import(import)) = item else {
+            continue;
+        };
+        if import.type_only {
+            continue;
+        }
+        let namespace = import_namespace(&import.src, &mut imports);
+        for specifier in import.specifiers {
+            match specifier {
+                ImportSpecifier::Named(named) if named.is_type_only => {}
+                ImportSpecifier::Named(named) => {
+                    let name = match &named.imported {
+                        Some(name) => export_name(name),
+                        None => named.local.sym.to_string(),
+                    };
+                    imported.insert(named.local.to_id(), member(&namespace, &name));
+                }
+                ImportSpecifier::Default(default) => {
+                    imported.insert(default.local.to_id(), member(&namespace, "default"));
+                }
+                ImportSpecifier::Namespace(star) => {
+                    let value = Expr::Ident(namespace.clone());
+                    imports.push(const_decl(Pat::Ident(star.local.into()), value));
+                }
+            }
+        }
+    }
+
+    for item in items {
+        let decl = match item {
+            ModuleItem::Stmt(stmt) => {
+                body.push(stmt);
+                continue;
+            }
+            ModuleItem::ModuleDecl(decl) => decl,
+        };
+        match decl {
+            ModuleDecl::ExportDecl(export) => {
+                for name in declared_names(&export.decl) {
+                    exports.push(getter(&name.sym, Expr::Ident(name.clone())));
+                }
+                body.push(Stmt::Decl(export.decl));
+            }
+            ModuleDecl::ExportNamed(export) if !export.type_only => {
+                let namespace = export
+                    .src
+                    .as_ref()
+                    .map(|src| import_namespace(src, &mut imports));
+                for specifier in export.specifiers {
+                    match specifier {
+                        ExportSpecifier::Named(named) if named.is_type_only => {}
+                        ExportSpecifier::Named(named) => {
+                            let orig = export_name(&named.orig);
+                            let exported =
+                                named.exported.as_ref().map_or(orig.clone(), export_name);
+                            // Export specifiers refer to top level bindings, so
+                            // an imported one is found by its name.
+                            let value = match &namespace {
+                                Some(namespace) => member(namespace, &orig),
+                                None => imported
+                                    .iter()
+                                    .find(|((sym, _), _)| &**sym == orig.as_str())
+                                    .map(|(_, value)| value.clone())
+                                    .unwrap_or_else(|| {
+                                        Expr::Ident(Ident::new(orig.as_str().into(), DUMMY_SP))
+                                    }),
+                            };
+                            exports.push(getter(&exported, value));
+                        }
+                        ExportSpecifier::Namespace(star) => {
+                            if let Some(namespace) = &namespace {
+                                let value = Expr::Ident(namespace.clone());
+                                exports.push(getter(&export_name(&star.name), value));
+                            }
+                        }
+                        ExportSpecifier::Default(default) => {
+                            if let Some(namespace) = &namespace {
+                                let value = member(namespace, "default");
+                                exports.push(getter(&default.exported.sym, value));
+                            }
+                        }
+                    }
+                }
+            }
+            ModuleDecl::ExportDefaultDecl(export) => {
+                let default = private_ident("__mainModuleDefault");
+                let ident = match export.decl {
+                    DefaultDecl::Fn(FnExpr { ident, function }) => {
+                        let ident = ident.unwrap_or(default);
+                        body.push(Stmt::Decl(Decl::Fn(FnDecl {
+                            ident: ident.clone(),
+                            declare: false,
+                            function,
+                        })));
+                        ident
+                    }
+                    DefaultDecl::Class(ClassExpr { ident, class }) => {
+                        let ident = ident.unwrap_or(default);
+                        body.push(Stmt::Decl(Decl::Class(ClassDecl {
+                            ident: ident.clone(),
+                            declare: false,
+                            class,
+                        })));
+                        ident
+                    }
+                    DefaultDecl::TsInterfaceDecl(_) => continue,
+                };
+                exports.push(getter("default", Expr::Ident(ident)));
+            }
+            ModuleDecl::ExportDefaultExpr(export) => {
+                let default = private_ident("__mainModuleDefault");
+                body.push(const_decl(Pat::Ident(default.clone().into()), *export.expr));
+                exports.push(getter("default", Expr::Ident(default)));
+            }
+            ModuleDecl::ExportAll(export) if !export.type_only => {
+                star_exports.push(import_namespace(&export.src, &mut imports));
+            }
+            // Type-only imports and exports, and TypeScript module syntax,
+            // which is gone once transpiled.
+            _ => {}
+        }
+    }
+
+    body.visit_mut_with(&mut ImportedBindings(&imported));
+    let namespace = private_ident("__mainModuleNamespace");
+    body.push(const_decl(
+        Pat::Ident(namespace.clone().into()),
+        Expr::Object(ObjectLit {
+            span: DUMMY_SP,
+            props: exports,
+        }),
+    ));
+    for star in star_exports {
+        body.push(Stmt::Expr(ExprStmt {
+            span: DUMMY_SP,
+            expr: Box::new(reexport_all(&namespace, &star)),
+        }));
+    }
+    body.push(Stmt::Return(ReturnStmt {
+        span: DUMMY_SP,
+        arg: Some(Box::new(Expr::Ident(namespace))),
+    }));
+    module.body = imports
+        .into_iter()
+        .chain(body)
+        .map(ModuleItem::Stmt)
+        .collect();
+    module
+}
+
+/// Replaces the references to imported bindings with the namespace members
+/// they are imported from.
+struct ImportedBindings<'a>(&'a HashMap<Id, Expr>);
+
+impl VisitMut for ImportedBindings<'_> {
+    fn visit_mut_expr(&mut self, n: &mut Expr) {
+        match n {
+            Expr::Ident(ident) => {
+                if let Some(value) = self.0.get(&ident.to_id()) {
+                    *n = value.clone();
+                }
+            }
+            _ => n.visit_mut_children_with(self),
+        }
+    }
+
+    fn visit_mut_prop(&mut self, n: &mut Prop) {
+        match n {
+            Prop::Shorthand(ident) => {
+                if let Some(value) = self.0.get(&ident.to_id()) {
+                    *n = Prop::KeyValue(KeyValueProp {
+                        key: PropName::Ident(ident.clone()),
+                        value: Box::new(value.clone()),
+                    });
+                }
+            }
+            _ => n.visit_mut_children_with(self),
+        }
+    }
+}
+
+/// Define a getter on `namespace` for every export of `star` but the default
+/// one, unless `namespace` already has one, like `export *` does:
+///
+/// ```js
+/// Object.keys(star).forEach((key) => key === "default" || key in namespace ||
+///   Object.defineProperty(namespace, key, { enumerable: true, get: () => star[key] }));
+/// ```
+fn reexport_all(namespace: &Ident, star: &Ident) -> Expr {
+    let key = private_ident("__mainModuleKey");
+    let key_expr = || Box::new(Expr::Ident(key.clone()));
+    let call = |callee: Expr, args: Vec<Expr>| {
+        Expr::Call(CallExpr {
+            span: DUMMY_SP,
+            callee: Callee::Expr(Box::new(callee)),
+            args: args
+                .into_iter()
+                .map(|arg| ExprOrSpread {
+                    spread: None,
+                    expr: Box::new(arg),
+                })
+                .collect(),
+            type_args: None,
+        })
+    };
+    let object_method = |name: &str| {
+        Expr::Member(MemberExpr {
+            span: DUMMY_SP,
+            obj: Box::new(Expr::Ident(private_ident("Object"))),
+            prop: MemberProp::Ident(private_ident(name)),
+        })
+    };
+    let arrow = |params: Vec<Pat>, body: Expr| {
+        Expr::Arrow(ArrowExpr {
+            span: DUMMY_SP,
+            params,
+            body: Box::new(BlockStmtOrExpr::Expr(Box::new(body))),
+            is_async: false,
+            is_generator: false,
+            type_params: None,
+            return_type: None,
+        })
+    };
+    let bin = |op: BinaryOp, left: Box<Expr>, right: Expr| {
+        Expr::Bin(BinExpr {
+            span: DUMMY_SP,
+            op,
+            left,
+            right: Box::new(right),
+        })
+    };
+    let descriptor = Expr::Object(ObjectLit {
+        span: DUMMY_SP,
+        props: vec![
+            PropOrSpread::Prop(Box::new(Prop::KeyValue(KeyValueProp {
+                key: PropName::Ident(private_ident("enumerable")),
+                value: Box::new(Expr::Lit(Lit::Bool(Bool {
+                    span: DUMMY_SP,
+                    value: true,
+                }))),
+            }))),
+            PropOrSpread::Prop(Box::new(Prop::KeyValue(KeyValueProp {
+                key: PropName::Ident(private_ident("get")),
+                value: Box::new(arrow(
+                    Vec::new(),
+                    Expr::Member(MemberExpr {
+                        span: DUMMY_SP,
+                        obj: Box::new(Expr::Ident(star.clone())),
+                        prop: MemberProp::Computed(ComputedPropName {
+                            span: DUMMY_SP,
+                            expr: key_expr(),
+                        }),
+                    }),
+                )),
+            }))),
+        ],
+    });
+    let define = call(
+        object_method("defineProperty"),
+        vec![Expr::Ident(namespace.clone()), *key_expr(), descriptor],
+    );
+    let is_default = bin(
+        BinaryOp::EqEqEq,
+        key_expr(),
+        Expr::Lit(Lit::Str(Str {
+            span: DUMMY_SP,
+            value: "default".into(),
+            raw: None,
+        })),
+    );
+    let is_defined = bin(BinaryOp::In, key_expr(), Expr::Ident(namespace.clone()));
+    let body = bin(
+        BinaryOp::LogicalOr,
+        Box::new(bin(BinaryOp::LogicalOr, Box::new(is_default), is_defined)),
+        define,
+    );
+    let keys = call(object_method("keys"), vec![Expr::Ident(star.clone())]);
+    call(
+        Expr::Member(MemberExpr {
+            span: DUMMY_SP,
+            obj: Box::new(keys),
+            prop: MemberProp::Ident(private_ident("forEach")),
+        }),
+        vec![arrow(vec![Pat::Ident(key.clone().into())], body)],
+    )
+}
+
+/// Import `src` dynamically into a new binding, returned.
+fn import_namespace(src: &Str, imports: &mut Vec<Stmt>) -> Ident {
+    let namespace = private_ident(&format!("__mainModuleImport{}", imports.len()));
+    imports.push(const_decl(
+        Pat::Ident(namespace.clone().into()),
+        dynamic_import(src),
+    ));
+    namespace
+}
+
+fn private_ident(name: &str) -> Ident {
+    Ident::new(name.into(), DUMMY_SP)
+}
+
+fn export_name(name: &ModuleExportName) -> String {
+    match name {
+        ModuleExportName::Ident(ident) => ident.sym.to_string(),
+        ModuleExportName::Str(s) => s.value.to_string(),
+    }
+}
+
+/// `await import(src)`
+fn dynamic_import(src: &Str) -> Expr {
+    Expr::Await(AwaitExpr {
+        span: DUMMY_SP,
+        arg: Box::new(Expr::Call(CallExpr {
+            span: DUMMY_SP,
+            callee: Callee::Import(Import { span: DUMMY_SP }),
+            args: vec![ExprOrSpread {
+                spread: None,
+                expr: Box::new(Expr::Lit(Lit::Str(Str {
+                    span: DUMMY_SP,
+                    value: src.value.clone(),
+                    raw: None,
+                }))),
+            }],
+            type_args: None,
+        })),
+    })
+}
+
+fn const_decl(name: Pat, init: Expr) -> Stmt {
+    Stmt::Decl(Decl::Var(Box::new(VarDecl {
+        span: DUMMY_SP,
+        kind: VarDeclKind::Const,
+        declare: false,
+        decls: vec![VarDeclarator {
+            span: DUMMY_SP,
+            name,
+            init: Some(Box::new(init)),
+            definite: false,
+        }],
+    })))
+}
+
+/// `namespace[name]`
+fn member(namespace: &Ident, name: &str) -> Expr {
+    Expr::Member(MemberExpr {
+        span: DUMMY_SP,
+        obj: Box::new(Expr::Ident(namespace.clone())),
+        prop: MemberProp::Computed(ComputedPropName {
+            span: DUMMY_SP,
+            expr: Box::new(Expr::Lit(Lit::Str(Str {
+                span: DUMMY_SP,
+                value: name.into(),
+                raw: None,
+            }))),
+        }),
+    })
+}
+
+/// `get [name]() { return value; }`
+fn getter(name: &str, value: Expr) -> PropOrSpread {
+    PropOrSpread::Prop(Box::new(Prop::Getter(GetterProp {
+        span: DUMMY_SP,
+        key: PropName::Str(Str {
+            span: DUMMY_SP,
+            value: name.into(),
+            raw: None,
+        }),
+        type_ann: None,
+        body: Some(BlockStmt {
+            span: DUMMY_SP,
+            stmts: vec![Stmt::Return(ReturnStmt {
+                span: DUMMY_SP,
+                arg: Some(Box::new(value)),
+            })],
+        }),
+    })))
+}
+
+/// The bindings declared by an exported declaration.
+fn declared_names(decl: &Decl) -> Vec<Ident> {
+    match decl {
+        Decl::Class(class) => vec![class.ident.clone()],
+        Decl::Fn(function) => vec![function.ident.clone()],
+        Decl::Var(var) => {
+            let mut names = Vec::new();
+            for declarator in &var.decls {
+                pat_names(&declarator.name, &mut names);
+            }
+            names
+        }
+        _ => Vec::new(),
+    }
+}
+
+fn pat_names(pat: &Pat, names: &mut Vec<Ident>) {
+    match pat {
+        Pat::Ident(ident) => names.push(ident.id.clone()),
+        Pat::Array(array) => {
+            for elem in array.elems.iter().flatten() {
+                pat_names(elem, names);
+            }
+        }
+        Pat::Object(object) => {
+            for prop in &object.props {
+                match prop {
+                    ObjectPatProp::KeyValue(prop) => pat_names(&prop.value, names),
+                    ObjectPatProp::Assign(prop) => names.push(prop.key.clone()),
+                    ObjectPatProp::Rest(rest) => pat_names(&rest.arg, names),
+                }
+            }
+        }
+        Pat::Assign(assign) => pat_names(&assign.left, names),
+        Pat::Rest(rest) => pat_names(&rest.arg, names),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::{output::render_layout, BundleType};
+    use deno_ast::swc::{
+        codegen::{text_writer::JsWriter, Emitter},
+        common::{sync::Lrc, FileName, SourceMap},
+        parser::{lexer::Lexer, Parser, StringInput, Syntax},
+    };
+
+    fn parse(cm: &Lrc<SourceMap>, source: &str, module: bool) -> Result<Program, String> {
+        let fm = cm.new_source_file(FileName::Anon, source.to_string());
+        let lexer = Lexer::new(
+            Syntax::Es(Default::default()),
+            EsVersion::latest(),
+            StringInput::from(&*fm),
+            None,
+        );
+        let mut parser = Parser::new_from(lexer);
+        let program = if module {
+            parser.parse_module().map(Program::Module)
+        } else {
+            parser.parse_script().map(Program::Script)
+        };
+        match (program, parser.take_errors().is_empty()) {
+            (Ok(program), true) => Ok(program),
+            (Ok(_), false) => Err("recoverable syntax error".to_string()),
+            (Err(err), _) => Err(format!("{:?}", err.into_kind())),
+        }
+    }
+
+    #[test]
+    fn main_module_body_should_be_valid_inside_the_layout() {
+        let cm: Lrc<SourceMap> = Default::default();
+        let source = r#"
+import { h as createElement } from "https://esm.sh/preact";
+import * as path from "node:path";
+export * from "https://deno.land/std/fmt/colors.ts";
+export const [first, { second }] = [1, { second: 2 }];
+export function handler() { return createElement("div", null, path.sep); }
+export { handler as default, first as "first name", createElement };
+"#;
+        let Ok(Program::Module(module)) = parse(&cm, source, true) else {
+            panic!("invalid test module");
+        };
+        let module = to_function_body(module);
+        assert!(module
+            .body
+            .iter()
+            .all(|item| matches!(item, ModuleItem::Stmt(_))));
+
+        let mut buf = Vec::new();
+        Emitter {
+            cfg: Default::default(),
+            cm: cm.clone(),
+            comments: None,
+            wr: JsWriter::new(cm.clone(), "\n", &mut buf, None),
+        }
+        .emit_module(&module)
+        .unwrap();
+        let body = String::from_utf8(buf).unwrap();
+        assert!(body.contains("await import(\"https://esm.sh/preact\")"));
+        assert!(body.contains("get \"first name\"()"));
+        // Imported bindings are read from the namespace, so they stay live.
+        assert!(
+            body.contains("return __mainModuleImport0[\"h\"]("),
+            "{}",
+            body
+        );
+        assert!(!body.contains("createElement ="), "{}", body);
+        assert!(body.contains("get \"createElement\"() {"), "{}", body);
+        assert!(
+            body.contains("Object.defineProperty(__mainModuleNamespace"),
+            "{}",
+            body
+        );
+
+        let (prologue, epilogue) = render_layout(BundleType::MainModule, false).unwrap();
+        let wrapped = format!("{}{}{}", prologue, body, epilogue);
+        assert_eq!(parse(&cm, &wrapped, false).err(), None, "{}", wrapped);
+    }
+}
@@ -0,0 +1,98 @@
+use deno_core::{error::AnyError, serde_json, ModuleSpecifier};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use super::{map_file_path, write_bundle, write_file, BundleEmit};
+
+/// The name of the build manifest written next to the bundle.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// The number of hex digits of the content hash used in file names.
+const FILE_NAME_HASH_LEN: usize = 16;
+
+/// Describes a written bundle, for deploy steps to upload and reference it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildManifest {
+    pub entry_point: String,
+    /// The bundle file, relative to the manifest.
+    pub file: String,
+    /// The source map file, relative to the manifest.
+    pub map_file: Option<String>,
+    /// The size of the bundle in bytes.
+    pub size: usize,
+    /// The hex encoded SHA-256 of the bundle.
+    pub hash: String,
+    /// The modules included in the bundle.
+    pub modules: Vec<String>,
+}
+
+/// Write `emit` to `out_file` and describe it in a `manifest.json` in the same
+/// directory. With `hash_file_names`, the content hash of the code is added to
+/// the file name, so `main.js` is written as `main.<hash>.js`.
+pub fn write_with_manifest(
+    entry_point: &ModuleSpecifier,
+    out_file: &Path,
+    emit: &BundleEmit,
+    hash_file_names: bool,
+) -> Result<BuildManifest, AnyError> {
+    let hash = format!("{:x}", Sha256::digest(emit.code.as_bytes()));
+    let out_file = if hash_file_names {
+        hashed_file_path(out_file, &hash[..FILE_NAME_HASH_LEN])
+    } else {
+        out_file.to_path_buf()
+    };
+    write_bundle(&out_file, emit)?;
+
+    let file_name = |path: &Path| {
+        path.file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    };
+    let manifest = BuildManifest {
+        entry_point: entry_point.to_string(),
+        file: file_name(&out_file),
+        map_file: emit
+            .maybe_map
+            .as_ref()
+            .map(|_| file_name(&map_file_path(&out_file))),
+        size: emit.code.len(),
+        hash,
+        modules: emit.modules.iter().map(|m| m.to_string()).collect(),
+    };
+    let manifest_json = serde_json::to_string_pretty(&manifest)?;
+    let manifest_file = out_file.with_file_name(MANIFEST_FILE);
+    write_file(&manifest_file, manifest_json.as_bytes(), 0o644)?;
+    Ok(manifest)
+}
+
+/// Insert `hash` before the extension of the file name of `path`.
+fn hashed_file_path(path: &Path, hash: &str) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let file_name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, hash, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, hash),
+    };
+    path.with_file_name(file_name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashed_file_path_should_keep_the_extension() {
+        assert_eq!(
+            hashed_file_path(Path::new("dist/main.js"), "0123abcd"),
+            PathBuf::from("dist/main.0123abcd.js")
+        );
+        assert_eq!(
+            hashed_file_path(Path::new("dist/main"), "0123abcd"),
+            PathBuf::from("dist/main.0123abcd")
+        );
+    }
+}
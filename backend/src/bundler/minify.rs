@@ -1,37 +1,171 @@
-// use deno_ast::swc;
-// use deno_core::serde_json;
-// use swc::{
-//     bundler::Bundle,
-//     common::{sync::Lrc, Mark, SourceMap},
-//     transforms::fixer,
-//     visit::VisitMutWith,
-// };
-// use swc_ecma_minifier::{
-//     optimize,
-//     option::{ExtraOptions, MinifyOptions},
-// };
-
-// const MINIFY_CONFIG: &str = include_str!("config.json");
-
-// pub fn minify(cm: Lrc<SourceMap>, modules: Vec<Bundle>) -> Vec<Bundle> {
-//     let options: MinifyOptions = serde_json::from_str(MINIFY_CONFIG).unwrap();
-//     modules
-//         .into_iter()
-//         .map(|mut b| {
-//             b.module = optimize(
-//                 b.module.into(),
-//                 cm.clone(),
-//                 None,
-//                 None,
-//                 &options,
-//                 &ExtraOptions {
-//                     unresolved_mark: Mark::fresh(Mark::root()),
-//                     top_level_mark: Mark::fresh(Mark::root()),
-//                 },
-//             )
-//             .expect_module();
-//             b.module.visit_mut_with(&mut fixer(None));
-//             b
-//         })
-//         .collect()
-// }
+use deno_ast::swc;
+use deno_core::{
+    error::AnyError,
+    serde_json::{self, Value},
+};
+use serde::Deserialize;
+use swc::{
+    bundler::Bundle,
+    common::{sync::Lrc, Mark, SourceMap},
+    transforms::{fixer, resolver},
+    visit::VisitMutWith,
+};
+use swc_ecma_minifier::{
+    optimize,
+    option::{terser::TerserCompressorOptions, ExtraOptions, MangleOptions, MinifyOptions},
+};
+
+use super::config::json_merge;
+
+const MINIFY_CONFIG: &str = include_str!("config.json");
+
+/// The minifier configuration, in the same shape as `config.json`.
+#[derive(Debug, Default, Deserialize)]
+struct MinifyConfig {
+    #[serde(default)]
+    compress: Option<TerserCompressorOptions>,
+    #[serde(default)]
+    mangle: Option<MangleOptions>,
+}
+
+impl MinifyConfig {
+    /// Load `config.json`, with `overrides` merged over it.
+    fn load(overrides: Option<&Value>) -> Result<Self, AnyError> {
+        let mut value: Value = serde_json::from_str(MINIFY_CONFIG)?;
+        if let Some(overrides) = overrides {
+            json_merge(&mut value, overrides);
+        }
+        Ok(serde_json::from_value(value)?)
+    }
+
+    fn into_options(self, cm: Lrc<SourceMap>) -> MinifyOptions {
+        MinifyOptions {
+            compress: self.compress.map(|c| c.into_config(cm)),
+            mangle: self.mangle,
+            ..Default::default()
+        }
+    }
+}
+
+/// Run the compress and mangle passes over the bundled modules.
+///
+/// Spans are kept by the minifier, so the source map generated afterward still
+/// points at the original modules. This must be called within the same swc
+/// `GLOBALS` that were used for bundling.
+pub fn minify(
+    cm: Lrc<SourceMap>,
+    modules: Vec<Bundle>,
+    overrides: Option<&Value>,
+) -> Result<Vec<Bundle>, AnyError> {
+    let options = MinifyConfig::load(overrides)?.into_options(cm.clone());
+    Ok(modules
+        .into_iter()
+        .map(|mut b| {
+            let unresolved_mark = Mark::fresh(Mark::root());
+            let top_level_mark = Mark::fresh(Mark::root());
+            b.module
+                .visit_mut_with(&mut resolver(unresolved_mark, top_level_mark, false));
+            b.module = optimize(
+                b.module.into(),
+                cm.clone(),
+                None,
+                None,
+                &options,
+                &ExtraOptions {
+                    unresolved_mark,
+                    top_level_mark,
+                },
+            )
+            .expect_module();
+            b.module.visit_mut_with(&mut fixer(None));
+            b
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bundler::{analyze::decode_vlq, bundle_with_options, BundleOptions};
+    use deno_core::{serde_json::json, ModuleSpecifier};
+
+    #[test]
+    fn default_minify_config_should_load() {
+        let config = MinifyConfig::load(None).unwrap();
+        assert!(config.compress.is_some());
+        assert!(config.mangle.is_some());
+    }
+
+    #[test]
+    fn minify_config_overrides_should_work() {
+        let config = MinifyConfig::load(Some(&json!({ "mangle": null }))).unwrap();
+        assert!(config.compress.is_some());
+        assert!(config.mangle.is_none());
+    }
+
+    /// The original line of the last mapping at or before `column` of the
+    /// generated `line`.
+    fn original_line(mappings: &str, line: usize, column: i64) -> Option<i64> {
+        let mut source_line = 0;
+        let mut found = None;
+        for (i, segments) in mappings.split(';').enumerate() {
+            let mut generated_column = 0;
+            for segment in segments.split(',').filter(|s| !s.is_empty()) {
+                let fields = decode_vlq(segment).unwrap();
+                generated_column += fields[0];
+                if fields.len() >= 4 {
+                    source_line += fields[2];
+                    if i == line && generated_column <= column {
+                        found = Some(source_line);
+                    }
+                }
+            }
+        }
+        found
+    }
+
+    #[tokio::test]
+    async fn minified_bundles_should_be_mangled_and_keep_their_source_map() {
+        let dir = std::env::temp_dir().join("deno_minify_test");
+        std::fs::create_dir_all(&dir).unwrap();
+        let entry = dir.join("main.ts");
+        std::fs::write(
+            &entry,
+            r#"export function greet(longParameterName: string) {
+  if (false) {
+    console.log("unreachable branch");
+  }
+  return "Hello, " + longParameterName;
+}
+"#,
+        )
+        .unwrap();
+        let mut options = BundleOptions {
+            minify: true,
+            module_store: None,
+            discover_config: false,
+            ..Default::default()
+        };
+        options.ts_config.merge(&json!({
+            "sourceMap": true,
+            "inlineSourceMap": false,
+        }));
+        let emit = bundle_with_options(ModuleSpecifier::from_file_path(&entry).unwrap(), &options)
+            .await
+            .unwrap();
+        assert!(!emit.code.contains("longParameterName"), "{}", emit.code);
+        assert!(!emit.code.contains("unreachable branch"), "{}", emit.code);
+
+        let source_map: Value = serde_json::from_str(&emit.maybe_map.unwrap()).unwrap();
+        let (line, text) = emit
+            .code
+            .lines()
+            .enumerate()
+            .find(|(_, text)| text.contains("Hello, "))
+            .unwrap();
+        // The opening quote of the string literal.
+        let column = text.find("Hello, ").unwrap() as i64 - 1;
+        let mappings = source_map["mappings"].as_str().unwrap();
+        assert_eq!(original_line(mappings, line, column), Some(4));
+    }
+}
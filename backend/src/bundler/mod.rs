@@ -1,21 +1,55 @@
 use askama::Template;
-use deno_core::{error::AnyError, ModuleSpecifier};
-use deno_graph::{GraphKind, ModuleGraph};
+use deno_ast::swc;
+use deno_core::{
+    anyhow::{bail, Context},
+    error::AnyError,
+    serde_json::{self, json, Value},
+    ModuleSpecifier,
+};
+use deno_graph::{source::Loader, BuildOptions, GraphKind, ModuleGraph};
 use derive_builder::Builder;
-use std::{fs::OpenOptions, sync::Arc};
+use import_map::ImportMap;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::OpenOptions,
+    rc::Rc,
+    sync::Arc,
+};
 use std::{
     io::Write,
     path::{Path, PathBuf},
 };
 
-use crate::utils::ModuleStore;
+use crate::utils::{
+    fetch::FetchClient,
+    node::NodeBuiltins,
+    npm::{NpmLoader, NpmRegistry, NpmResolver},
+    CachePolicy, ModuleResolver, ModuleStore, UniversalModuleLoader,
+};
 
+use hook::BundleHook;
+use loader::{BundleLoader, TranspileCache};
+use resolver::BundleResolver;
+
+pub mod analyze;
+pub mod budget;
+pub mod chunk;
 mod config;
+pub mod define;
+pub mod diagnostics;
+pub mod external;
+pub mod graph;
 pub mod hook;
 pub mod loader;
+pub mod lockfile;
+mod main_module;
+pub mod manifest;
 pub mod minify;
 pub mod options;
 pub mod output;
+pub mod resolver;
+pub mod vendor;
+pub mod watch;
 
 pub use config::*;
 
@@ -39,6 +73,87 @@ pub struct BundleOptions {
     pub emit_ignore_directives: bool,
     pub module_store: Option<Arc<dyn ModuleStore>>,
     pub minify: bool,
+    /// Overrides merged over the minifier's `config.json` when `minify` is set.
+    pub minify_config: Option<Value>,
+    /// The import map applied when resolving specifiers, see
+    /// `utils::load_import_map_file` and `utils::load_import_map_value`.
+    pub import_map: Option<Arc<ImportMap>>,
+    /// The `deno.json` or `tsconfig.json` whose `compilerOptions` are merged over
+    /// `ts_config`. When unset and `discover_config` is enabled, it is looked up
+    /// next to the entry point and in its ancestors.
+    pub config_file: Option<PathBuf>,
+    pub discover_config: bool,
+    /// Name the files written by `bundle_with_manifest` after the content hash
+    /// of the bundle.
+    pub hash_file_names: bool,
+    /// The size limits checked by `bundle_with_options`.
+    pub size_budget: Option<budget::SizeBudget>,
+    /// The modules left out of the bundle, which keeps importing them.
+    pub externals: external::Externals,
+    /// Global expressions, such as `process.env.NODE_ENV` or `__DEV__`, replaced
+    /// with the given values while transpiling. The branches made unreachable by
+    /// the replaced values are removed.
+    pub define: HashMap<String, Value>,
+    /// Extra properties of `import.meta` in the bundled modules, such as a
+    /// deployment ID or the build time.
+    pub import_meta: BTreeMap<String, Value>,
+    /// Fail when a JSON module is imported without the `{ type: "json" }`
+    /// attribute.
+    pub strict_json_imports: bool,
+    /// The registry `npm:` specifiers are downloaded from.
+    pub npm_registry: NpmRegistry,
+    /// The modules providing the Node.js built-ins imported with `node:`
+    /// specifiers. The built-ins provided by the runtime are kept as imports.
+    pub node_builtins: NodeBuiltins,
+    /// The lockfile the remote modules of the graph are verified against. It
+    /// is written on the first bundle, and extended with new remote modules.
+    pub lock_file: Option<PathBuf>,
+    /// Replace the lockfile with the remote modules of the graph instead of
+    /// verifying them.
+    pub lock_write: bool,
+    /// Whether remote modules are loaded from `module_store` or fetched again.
+    pub cache_policy: CachePolicy,
+    /// The HTTP client remote modules and npm packages are fetched with.
+    pub fetch_client: FetchClient,
+}
+
+/// The emitted bundle, along with its source map when one was requested.
+#[derive(Debug, Clone)]
+pub struct BundleEmit {
+    pub code: String,
+    pub maybe_map: Option<String>,
+    /// The modules included in the bundle.
+    pub modules: Vec<ModuleSpecifier>,
+}
+
+/// The swc state of a series of bundles of the same project. Modules that
+/// haven't changed since the previous bundle of a session are not transpiled
+/// again.
+struct BundleSession {
+    globals: swc::common::Globals,
+    cm: Rc<swc::common::SourceMap>,
+    transpile_cache: TranspileCache,
+}
+
+impl Default for BundleSession {
+    fn default() -> Self {
+        Self {
+            globals: swc::common::Globals::new(),
+            cm: Rc::new(swc::common::SourceMap::new(
+                swc::common::FilePathMapping::empty(),
+            )),
+            transpile_cache: TranspileCache::default(),
+        }
+    }
+}
+
+impl BundleSession {
+    /// Whether the source map holds many more files than the cached modules.
+    /// The files of the previous versions of changed modules are never removed
+    /// from it, so a long-running session is replaced once it is bloated.
+    fn is_bloated(&self) -> bool {
+        self.cm.files().len() > 2 * self.transpile_cache.len() + 64
+    }
 }
 
 #[derive(Template)]
@@ -52,41 +167,303 @@ pub async fn bundle(
     module_specifier: ModuleSpecifier,
     out_file: Option<PathBuf>,
 ) -> Result<(), AnyError> {
-    let roots = vec![module_specifier.clone()];
-    let mut graph = ModuleGraph::new(GraphKind::All);
-    graph.build(roots, &mut loader, Default::default()).await;
-    let bundle_output = bundle_module_graph(graph.as_ref())?;
+    let bundle_output = bundle_with_options(module_specifier, &BundleOptions::default()).await?;
     if let Some(out_file) = out_file {
-        let output_bytes = bundle_output.code.as_bytes();
-        let output_len = output_bytes.len();
-        write_file(&out_file, output_bytes, 0o644)?;
-        if let Some(bundle_map) = bundle_output.maybe_map {
-            let map_bytes = bundle_map.as_bytes();
-            let map_len = map_bytes.len();
-            let ext = if let Some(curr_ext) = out_file.extension() {
-                format!("{}.map", curr_ext.to_string_lossy())
-            } else {
-                "map".to_string()
-            };
-            let map_out_file = out_file.with_extension(ext);
-            write_file(&map_out_file, map_bytes, 0o644)?;
-        }
+        write_bundle(&out_file, &bundle_output)?;
     } else {
         println!("{}", bundle_output.code);
     }
     Ok(())
 }
 
-fn bundle_module_graph(graph: &deno_graph::ModuleGraph) -> Result<deno_emit::BundleEmit, AnyError> {
-    let ts_config = get_ts_config()?;
-    deno_emit::bundle_graph(
-        graph,
-        deno_emit::BundleOptions {
-            bundle_type: deno_emit::BundleType::Module,
-            emit_options: ts_config.into(),
-            emit_ignore_directives: true,
-        },
-    )
+/// Build the module graph rooted at `module_specifier` and bundle it according
+/// to `options`.
+pub async fn bundle_with_options(
+    module_specifier: ModuleSpecifier,
+    options: &BundleOptions,
+) -> Result<BundleEmit, AnyError> {
+    let options = &resolve_options(options, &module_specifier)?;
+    let graph = create_graph(vec![module_specifier], options).await?;
+    let session = BundleSession::default();
+    let Some(size_budget) = &options.size_budget else {
+        return bundle_module_graph(&graph, options, &session);
+    };
+    if !size_budget.needs_report() {
+        let emit = bundle_module_graph(&graph, options, &session)?;
+        size_budget.check(&emit.code, None)?;
+        return Ok(emit);
+    }
+
+    // The per-module budget needs a source map, which is only kept in the
+    // output when it was requested.
+    let requested = EmitConfigOptions::try_from(&options.ts_config.0)?;
+    let mut analyzed_options = options.clone();
+    analyzed_options.ts_config.merge(&json!({
+        "sourceMap": true,
+        "inlineSourceMap": false,
+    }));
+    let mut emit = bundle_module_graph(&graph, &analyzed_options, &session)?;
+    let source_map = emit
+        .maybe_map
+        .take()
+        .context("A source map is required to check the size budget.")?;
+    let report = analyze::analyze(&emit.code, &source_map, &graph)?;
+    if requested.inline_source_map {
+        output::append_inline_source_map(&mut emit.code, source_map.as_bytes());
+    } else if requested.source_map {
+        emit.maybe_map = Some(source_map);
+    }
+    size_budget.check(&emit.code, Some(&report))?;
+    Ok(emit)
+}
+
+/// Bundle `module_specifier` into `out_file`, and describe the output in a
+/// `manifest.json` next to it. See `BundleOptions::hash_file_names`.
+pub async fn bundle_with_manifest(
+    module_specifier: ModuleSpecifier,
+    out_file: &Path,
+    options: &BundleOptions,
+) -> Result<manifest::BuildManifest, AnyError> {
+    let emit = bundle_with_options(module_specifier.clone(), options).await?;
+    manifest::write_with_manifest(&module_specifier, out_file, &emit, options.hash_file_names)
+}
+
+/// Bundle `module_specifier` and attribute the size of the bundle to the modules
+/// it includes. A source map is always generated for the analysis.
+pub async fn analyze_with_options(
+    module_specifier: ModuleSpecifier,
+    options: &BundleOptions,
+) -> Result<analyze::SizeReport, AnyError> {
+    let mut options = resolve_options(options, &module_specifier)?;
+    options.ts_config.merge(&json!({
+        "sourceMap": true,
+        "inlineSourceMap": false,
+    }));
+    let graph = create_graph(vec![module_specifier], &options).await?;
+    let emit = bundle_module_graph(&graph, &options, &BundleSession::default())?;
+    let source_map = emit
+        .maybe_map
+        .context("A source map is required to analyze the bundle.")?;
+    analyze::analyze(&emit.code, &source_map, &graph)
+}
+
+/// Bundle several entry points, splitting dynamic imports and the modules
+/// shared between entry points into separate chunks. The chunks are written
+/// into `out_dir` along with a `chunk-manifest.json` describing them.
+pub async fn bundle_chunks(
+    entries: Vec<ModuleSpecifier>,
+    out_dir: &Path,
+    options: &BundleOptions,
+) -> Result<chunk::ChunkManifest, AnyError> {
+    if options.bundle_type != BundleType::Module {
+        bail!("Code splitting is only supported for the `Module` bundle type.");
+    }
+    let Some(first_entry) = entries.first() else {
+        bail!("No entry points to bundle.");
+    };
+    let options = &resolve_options(options, first_entry)?;
+    let graph = create_graph(entries.clone(), options).await?;
+    let plan = chunk::ChunkPlan::new(&graph, &entries, &options.externals);
+    std::fs::create_dir_all(out_dir)?;
+    let session = BundleSession::default();
+    for root in plan.roots() {
+        let rewrites = plan.rewrites_for(root);
+        let emit = bundle_entry(&graph, root, &rewrites, options, &session)?;
+        write_bundle(&out_dir.join(plan.file_name(root)), &emit)?;
+    }
+    let manifest = plan.manifest();
+    let manifest_json = serde_json::to_string_pretty(&manifest)?;
+    write_file(
+        &out_dir.join(chunk::CHUNK_MANIFEST_FILE),
+        manifest_json.as_bytes(),
+        0o644,
+    )?;
+    Ok(manifest)
+}
+
+/// Merge the compiler options of the project config file, when there is one,
+/// over the `ts_config` of `options`, and add the Node.js built-ins provided by
+/// the runtime to the externals.
+fn resolve_options(
+    options: &BundleOptions,
+    entry: &ModuleSpecifier,
+) -> Result<BundleOptions, AnyError> {
+    let config_file = match &options.config_file {
+        Some(config_file) => Some(config_file.clone()),
+        None if options.discover_config => discover_config_file(entry),
+        None => None,
+    };
+    let mut options = options.clone();
+    if let Some(config_file) = config_file {
+        options.ts_config.merge_config_file(&config_file)?;
+    }
+    let node_externals: Vec<String> = options
+        .node_builtins
+        .external_names()
+        .map(|name| format!("node:{}", name))
+        .collect();
+    options.externals.extend(node_externals);
+    Ok(options)
+}
+
+/// Build the module graph for `roots`, failing with every module that could not
+/// be resolved or loaded.
+async fn create_graph(
+    roots: Vec<ModuleSpecifier>,
+    options: &BundleOptions,
+) -> Result<ModuleGraph, AnyError> {
+    let mut loader = UniversalModuleLoader::new(options.module_store.clone(), false)
+        .with_cache_policy(options.cache_policy.clone())
+        .with_fetch_client(options.fetch_client.clone());
+    let graph = build_graph(roots, options, &mut loader).await;
+    graph::validate_graph(&graph, options.strict_json_imports)?;
+    if let Some(lock_file) = &options.lock_file {
+        lockfile::check_lockfile(&graph, lock_file, options.lock_write)?;
+    }
+    Ok(graph)
+}
+
+/// Build the module graph for `roots`, loading modules with `loader`. The graph
+/// is not validated.
+async fn build_graph(
+    roots: Vec<ModuleSpecifier>,
+    options: &BundleOptions,
+    loader: &mut dyn Loader,
+) -> ModuleGraph {
+    // Bundling resolves imports from the graph, so resolving through the import
+    // map here applies it to the bundle as well.
+    let npm = Arc::new(
+        NpmResolver::new(options.npm_registry.clone(), options.module_store.clone())
+            .with_fetch_client(options.fetch_client.clone())
+            .with_cache_policy(options.cache_policy.clone()),
+    );
+    let resolver = ModuleResolver::new(options.import_map.clone())
+        .with_npm(npm.clone())
+        .with_node_builtins(Arc::new(options.node_builtins.clone()));
+    let mut loader = NpmLoader { inner: loader, npm };
+    let mut loader = external::ExternalLoader {
+        inner: &mut loader,
+        externals: &options.externals,
+    };
+    let mut graph = ModuleGraph::new(GraphKind::All);
+    graph
+        .build(
+            roots,
+            &mut loader,
+            BuildOptions {
+                resolver: Some(&resolver),
+                ..Default::default()
+            },
+        )
+        .await;
+    graph
+}
+
+fn bundle_module_graph(
+    graph: &deno_graph::ModuleGraph,
+    options: &BundleOptions,
+    session: &BundleSession,
+) -> Result<BundleEmit, AnyError> {
+    bundle_entry(graph, &graph.roots[0], &HashMap::new(), options, session)
+}
+
+/// Bundle the modules reachable from `entry`. Imports of the modules in
+/// `rewrites` and of external modules are kept in the output, pointing at the
+/// rewritten specifier.
+fn bundle_entry(
+    graph: &deno_graph::ModuleGraph,
+    entry: &ModuleSpecifier,
+    rewrites: &HashMap<ModuleSpecifier, String>,
+    options: &BundleOptions,
+    session: &BundleSession,
+) -> Result<BundleEmit, AnyError> {
+    let mut rewrites = rewrites.clone();
+    if !options.externals.is_empty() {
+        for (specifier, _) in graph.specifiers() {
+            if options.externals.matches(specifier.as_str()) {
+                rewrites.insert(specifier.clone(), specifier.to_string());
+            }
+        }
+    }
+    let rewrites = &rewrites;
+    let emit_options: deno_ast::EmitOptions = options.ts_config.clone().try_into()?;
+    let defines = define::Defines::new(&options.define)?;
+    let globals = &session.globals;
+    swc::common::GLOBALS.set(globals, || {
+        let cm = session.cm.clone();
+        let loader = BundleLoader::new(
+            cm.clone(),
+            &emit_options,
+            &defines,
+            graph,
+            rewrites,
+            &session.transpile_cache,
+        );
+        let modules = loader.preload(entry)?;
+        let resolver = BundleResolver::new(graph, rewrites);
+        let config = swc::bundler::Config {
+            require: false,
+            disable_inliner: false,
+            disable_hygiene: false,
+            disable_fixer: false,
+            disable_dce: false,
+            external_modules: rewrites.values().map(|s| s.as_str().into()).collect(),
+            module: options.bundle_type.into(),
+        };
+        // This hook will rewrite the `import.meta` when bundling to give a consistent
+        // behavior between bundled and unbundled code.
+        let hook = Box::new(
+            BundleHook::new(options.import_meta.clone())
+                .with_resolutions(graph, options.import_map.as_deref()),
+        );
+        let mut bundler =
+            swc::bundler::Bundler::new(globals, cm.clone(), loader, resolver, config, hook);
+        let mut entries = HashMap::new();
+        entries.insert(
+            "bundle".to_string(),
+            swc::common::FileName::Url(entry.clone()),
+        );
+        let output = bundler
+            .bundle(entries)
+            .context("Unable to output during bundling.")?;
+        let output = if options.minify {
+            minify::minify(cm.clone(), output, options.minify_config.as_ref())?
+        } else {
+            output
+        };
+        let (code, maybe_map) = output::gen_code(
+            cm,
+            &output[0],
+            &emit_options,
+            options.bundle_type,
+            options.emit_ignore_directives,
+            options.minify,
+        )?;
+        Ok(BundleEmit {
+            code,
+            maybe_map,
+            modules,
+        })
+    })
+}
+
+/// Write the bundled code to `out_file`, and its source map next to it.
+fn write_bundle(out_file: &Path, emit: &BundleEmit) -> Result<(), AnyError> {
+    write_file(out_file, emit.code.as_bytes(), 0o644)?;
+    if let Some(bundle_map) = &emit.maybe_map {
+        write_file(&map_file_path(out_file), bundle_map.as_bytes(), 0o644)?;
+    }
+    Ok(())
+}
+
+/// The path of the source map written next to `out_file`.
+fn map_file_path(out_file: &Path) -> PathBuf {
+    let ext = if let Some(curr_ext) = out_file.extension() {
+        format!("{}.map", curr_ext.to_string_lossy())
+    } else {
+        "map".to_string()
+    };
+    out_file.with_extension(ext)
 }
 
 fn write_file<T: AsRef<[u8]>>(filename: &Path, data: T, mode: u32) -> std::io::Result<()> {
@@ -29,6 +29,22 @@ impl Default for BundleOptions {
             emit_ignore_directives: false,
             module_store: Some(Arc::new(FsModuleStore::default())),
             minify: true,
+            minify_config: None,
+            import_map: None,
+            config_file: None,
+            discover_config: true,
+            hash_file_names: false,
+            size_budget: None,
+            externals: Default::default(),
+            define: Default::default(),
+            import_meta: Default::default(),
+            strict_json_imports: false,
+            npm_registry: Default::default(),
+            node_builtins: Default::default(),
+            lock_file: None,
+            lock_write: false,
+            cache_policy: Default::default(),
+            fetch_client: Default::default(),
         }
     }
 }
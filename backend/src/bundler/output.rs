@@ -1,10 +1,17 @@
+use askama::Template;
 use base64::{engine::general_purpose, Engine};
 use deno_ast::{
-    swc::{self, bundler::Bundle, common::sync::Lrc, common::SourceMap},
+    swc::{
+        self,
+        bundler::Bundle,
+        common::{sync::Lrc, BytePos, LineCol, SourceMap},
+    },
     EmitOptions,
 };
 use deno_core::{anyhow::Context, error::AnyError};
 
+use super::{main_module, BundleType, BundledJs};
+
 const IGNORE_DIRECTIVES: &[&str] = &[
     "// deno-fmt-ignore-file",
     "// deno-lint-ignore-file",
@@ -12,10 +19,15 @@ const IGNORE_DIRECTIVES: &[&str] = &[
     "",
 ];
 
+/// Stands in for the bundle body when rendering `layout.j2`, so the emitted code
+/// can be spliced in without passing it through the template engine.
+const BODY_PLACEHOLDER: &str = "__TINY_DEPLOY_BUNDLE_BODY__";
+
 pub fn gen_code(
     cm: Lrc<SourceMap>,
     bundle: &Bundle,
     emit_options: &EmitOptions,
+    bundle_type: BundleType,
     ignore_directive: bool,
     minify: bool,
 ) -> Result<(String, Option<String>), AnyError> {
@@ -36,7 +48,7 @@ pub fn gen_code(
             Some(&mut srcmap),
         ));
 
-        if ignore_directive {
+        if ignore_directive && !is_wrapped(bundle_type) {
             // write leading comments in bundled file
             use swc::codegen::text_writer::WriteJs;
             let cmt = IGNORE_DIRECTIVES.join("\n") + "\n";
@@ -49,11 +61,24 @@ pub fn gen_code(
             comments: None,
             wr,
         };
+        // `import` and `export` are not allowed in the body of `mainModule`.
+        let main_module_body;
+        let module = if bundle_type == BundleType::MainModule {
+            main_module_body = main_module::to_function_body(bundle.module.clone());
+            &main_module_body
+        } else {
+            &bundle.module
+        };
         emitter
-            .emit_module(&bundle.module)
+            .emit_module(module)
             .context("Unable to emit during bundling.")?;
     }
     let mut code = String::from_utf8(buf).context("Emitted code is an invalid string.")?;
+    if is_wrapped(bundle_type) {
+        let (prologue, epilogue) = render_layout(bundle_type, ignore_directive)?;
+        shift_mappings(&mut srcmap, &prologue);
+        code = format!("{}{}{}", prologue, code, epilogue);
+    }
 
     let mut maybe_map: Option<String> = None;
     if emit_options.source_map || emit_options.inline_source_map {
@@ -61,11 +86,7 @@ pub fn gen_code(
         cm.build_source_map_with_config(&mut srcmap, None, source_map_config)
             .to_writer(&mut buf)?;
         if emit_options.inline_source_map {
-            let encoded_map = format!(
-                "//# sourceMappingURL=data:application/json;base64,{}\n",
-                general_purpose::STANDARD.encode(buf)
-            );
-            code.push_str(&encoded_map);
+            append_inline_source_map(&mut code, &buf);
         } else if emit_options.source_map {
             maybe_map = Some(String::from_utf8(buf)?);
         }
@@ -73,3 +94,53 @@ pub fn gen_code(
 
     Ok((code, maybe_map))
 }
+
+/// Append `map` to `code` as an inline source map.
+pub fn append_inline_source_map(code: &mut String, map: &[u8]) {
+    let encoded_map = format!(
+        "//# sourceMappingURL=data:application/json;base64,{}\n",
+        general_purpose::STANDARD.encode(map)
+    );
+    code.push_str(&encoded_map);
+}
+
+/// Whether the bundle type is rendered through the `layout.j2` template.
+fn is_wrapped(bundle_type: BundleType) -> bool {
+    matches!(bundle_type, BundleType::MainModule | BundleType::Classic)
+}
+
+/// Render `layout.j2` for `bundle_type`, returning the code that goes before and
+/// after the bundle body.
+pub(super) fn render_layout(
+    bundle_type: BundleType,
+    ignore_directive: bool,
+) -> Result<(String, String), AnyError> {
+    let layout = BundledJs {
+        body: BODY_PLACEHOLDER.to_string(),
+        bundle_type,
+    }
+    .render()
+    .context("Unable to render the bundle layout.")?;
+    let (prologue, epilogue) = layout
+        .split_once(BODY_PLACEHOLDER)
+        .context("The bundle layout does not contain the body.")?;
+    let prologue = if ignore_directive {
+        IGNORE_DIRECTIVES.join("\n") + "\n" + prologue
+    } else {
+        prologue.to_string()
+    };
+    Ok((prologue, epilogue.to_string()))
+}
+
+/// Move the generated positions of the source map entries past `prologue`.
+fn shift_mappings(srcmap: &mut [(BytePos, LineCol)], prologue: &str) {
+    let line_offset = prologue.matches('\n').count() as u32;
+    let last_line = prologue.rsplit('\n').next().unwrap_or_default();
+    let col_offset = last_line.encode_utf16().count() as u32;
+    for (_, pos) in srcmap.iter_mut() {
+        if pos.line == 0 {
+            pos.col += col_offset;
+        }
+        pos.line += line_offset;
+    }
+}
@@ -1,9 +1,19 @@
 use deno_ast::swc;
-use deno_core::{anyhow::anyhow, error::AnyError};
+use deno_core::{anyhow::anyhow, error::AnyError, ModuleSpecifier};
 use deno_graph::ModuleGraph;
+use std::collections::HashMap;
 
 /// A resolver implementation for swc that resolves specifiers from the graph.
-pub struct BundleResolver<'a>(pub &'a ModuleGraph);
+pub struct BundleResolver<'a> {
+    graph: &'a ModuleGraph,
+    rewrites: &'a HashMap<ModuleSpecifier, String>,
+}
+
+impl<'a> BundleResolver<'a> {
+    pub fn new(graph: &'a ModuleGraph, rewrites: &'a HashMap<ModuleSpecifier, String>) -> Self {
+        Self { graph, rewrites }
+    }
+}
 
 impl swc::bundler::Resolve for BundleResolver<'_> {
     fn resolve(
@@ -19,7 +29,11 @@ impl swc::bundler::Resolve for BundleResolver<'_> {
                 referrer
             );
         };
-        if let Some(specifier) = self.0.resolve_dependency(specifier, referrer, false) {
+        if self.rewrites.values().any(|rewrite| rewrite == specifier) {
+            // Rewritten specifiers are kept as imports in the output.
+            return Ok(swc::common::FileName::Custom(specifier.to_string()));
+        }
+        if let Some(specifier) = self.graph.resolve_dependency(specifier, referrer, false) {
             Ok(deno_ast::swc::common::FileName::Url(specifier.clone()))
         } else {
             Err(anyhow!(
@@ -0,0 +1,286 @@
+use deno_ast::MediaType;
+use deno_core::{
+    anyhow::{anyhow, bail},
+    error::AnyError,
+    serde_json::{self, Map, Value},
+    ModuleSpecifier,
+};
+use deno_graph::ModuleGraph;
+use import_map::{ImportMap, SpecifierMap};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+};
+
+use crate::utils::{npm::package_id, resolve_from_cwd};
+
+use super::{create_graph, resolve_options, write_file, BundleOptions};
+
+/// The name of the import map written into the vendor directory.
+pub const VENDOR_IMPORT_MAP_FILE: &str = "import_map.json";
+
+/// The outcome of `vendor`.
+#[derive(Debug, Clone)]
+pub struct VendorOutput {
+    /// The import map to bundle the vendored project with.
+    pub import_map_file: PathBuf,
+    /// The remote modules copied into the vendor directory.
+    pub modules: Vec<ModuleSpecifier>,
+}
+
+/// Copy every remote module of the graph rooted at `entry` into `out_dir`, in a
+/// tree mirroring their host and path, e.g. `deno.land/std@0.196.0/http/mod.ts`.
+///
+/// The import map written next to them redirects the remote URLs to the
+/// vendored files and includes the entries of the import map of `options`, so
+/// bundling with it doesn't need the network. npm packages are not vendored,
+/// so the graph must not import any.
+pub async fn vendor(
+    entry: ModuleSpecifier,
+    out_dir: &Path,
+    options: &BundleOptions,
+) -> Result<VendorOutput, AnyError> {
+    let options = resolve_options(options, &entry)?;
+    let graph = create_graph(vec![entry], &options).await?;
+    let npm_packages: BTreeSet<String> = graph
+        .modules()
+        .filter_map(|module| package_id(&module.specifier))
+        .collect();
+    if !npm_packages.is_empty() {
+        let npm_packages: Vec<String> = npm_packages.into_iter().collect();
+        bail!(
+            "npm packages can't be vendored, and bundling them needs the npm registry: {}. \
+             Import them from a CDN such as https://esm.sh instead.",
+            npm_packages.join(", ")
+        );
+    }
+    let out_dir = resolve_from_cwd(out_dir)?;
+    let base_url = ModuleSpecifier::from_directory_path(&out_dir)
+        .map_err(|_| anyhow!("Invalid vendor directory {}", out_dir.display()))?;
+
+    let mut vendored = BTreeMap::new();
+    for module in graph.modules() {
+        if !is_remote(&module.specifier) {
+            continue;
+        }
+        // Assets are vendored as the JavaScript modules generated for them,
+        // which are typed as such in the graph.
+        let path = local_path(&module.specifier, Some(module.media_type));
+        let content = module
+            .maybe_source
+            .as_deref()
+            .unwrap_or_default()
+            .as_bytes();
+        let file = out_dir.join(&path);
+        if let Some(parent) = file.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_file(&file, content, 0o644)?;
+        vendored.insert(module.specifier.clone(), path);
+    }
+
+    let import_map = vendor_import_map(&graph, &vendored, options.import_map.as_deref(), &base_url);
+    let import_map_file = out_dir.join(VENDOR_IMPORT_MAP_FILE);
+    let json = serde_json::to_string_pretty(&import_map)?;
+    write_file(&import_map_file, format!("{}\n", json).as_bytes(), 0o644)?;
+    Ok(VendorOutput {
+        import_map_file,
+        modules: vendored.into_keys().collect(),
+    })
+}
+
+fn is_remote(specifier: &ModuleSpecifier) -> bool {
+    matches!(specifier.scheme(), "http" | "https")
+}
+
+/// The directory of the modules of a host, e.g. `deno.land` or
+/// `localhost_8000`.
+fn host_dir(specifier: &ModuleSpecifier) -> String {
+    let host = specifier.host_str().unwrap_or("unknown");
+    match specifier.port() {
+        Some(port) => format!("{}_{}", host, port),
+        None => host.to_string(),
+    }
+}
+
+/// The path of a module in the vendor directory, which mirrors its URL. A query
+/// is replaced by its hash, and an extension matching `media_type` is added
+/// when the URL lacks one, so the vendored module is loaded as the same type.
+fn local_path(specifier: &ModuleSpecifier, media_type: Option<MediaType>) -> String {
+    let path = specifier.path();
+    let (dir, file_name) = path.rsplit_once('/').unwrap_or(("", path));
+    let mut file_name = if file_name.is_empty() {
+        "index".to_string()
+    } else {
+        file_name.to_string()
+    };
+    if let Some(query) = specifier.query() {
+        let hash = format!("{:x}", Sha256::digest(query.as_bytes()));
+        file_name = match file_name.rsplit_once('.') {
+            Some((stem, ext)) => format!("{}_{}.{}", stem, &hash[..8], ext),
+            None => format!("{}_{}", file_name, &hash[..8]),
+        };
+    }
+    if let Some(media_type) = media_type {
+        let file_media_type = MediaType::from_path(Path::new(&file_name));
+        let is_js =
+            |m: MediaType| matches!(m, MediaType::JavaScript | MediaType::Mjs | MediaType::Cjs);
+        if file_media_type != media_type && !(is_js(file_media_type) && is_js(media_type)) {
+            file_name.push_str(media_type.as_ts_extension());
+        }
+    }
+    format!("{}{}/{}", host_dir(specifier), dir, file_name)
+}
+
+/// The path a module would have in the vendor directory if it mirrored its URL
+/// exactly.
+fn mirrored_path(specifier: &ModuleSpecifier) -> String {
+    format!("{}{}", host_dir(specifier), specifier.path())
+}
+
+/// Build an import map redirecting the remote modules of `graph` to their
+/// vendored copies, merged with the rewritten entries of `user_import_map`.
+fn vendor_import_map(
+    graph: &ModuleGraph,
+    vendored: &BTreeMap<ModuleSpecifier, String>,
+    user_import_map: Option<&ImportMap>,
+    base_url: &ModuleSpecifier,
+) -> Value {
+    let mut imports = Map::new();
+    let mut scopes: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+
+    for specifier in vendored.keys() {
+        let host_dir = host_dir(specifier);
+        let origin = format!("{}/", specifier.origin().ascii_serialization());
+        let dir = format!("./{}/", host_dir);
+        imports.insert(origin, Value::String(dir.clone()));
+        // Root relative imports of vendored modules stay on their host.
+        let scope = scopes.entry(dir.clone()).or_default();
+        scope.insert("/".to_string(), Value::String(dir.clone()));
+        scope.insert(dir.clone(), Value::String(dir));
+    }
+    for (specifier, path) in vendored {
+        let has_query = specifier.query().is_some();
+        if *path == mirrored_path(specifier) && !has_query {
+            continue;
+        }
+        let target = Value::String(format!("./{}", path));
+        imports.insert(specifier.to_string(), target.clone());
+        // Relative and root relative imports of the module by other modules of
+        // the same host.
+        let query = specifier
+            .query()
+            .map(|query| format!("?{}", query))
+            .unwrap_or_default();
+        let scope = scopes
+            .entry(format!("./{}/", host_dir(specifier)))
+            .or_default();
+        scope.insert(
+            format!("./{}{}", mirrored_path(specifier), query),
+            target.clone(),
+        );
+        scope.insert(format!("{}{}", specifier.path(), query), target);
+    }
+    for (from, to) in &graph.redirects {
+        if let Some(path) = vendored.get(to) {
+            if is_remote(from) {
+                imports.insert(from.to_string(), Value::String(format!("./{}", path)));
+            }
+        }
+    }
+
+    if let Some(user_import_map) = user_import_map {
+        let rewrite = |map: &SpecifierMap, into: &mut Map<String, Value>| {
+            for entry in map.entries() {
+                if let Some(value) = entry.value {
+                    let value = vendored_address(graph, vendored, value, base_url);
+                    into.insert(entry.raw_key.to_string(), Value::String(value));
+                }
+            }
+        };
+        rewrite(user_import_map.imports(), &mut imports);
+        for scope in user_import_map.scopes() {
+            let Ok(scope_url) = ModuleSpecifier::parse(scope.key) else {
+                continue;
+            };
+            let key = vendored_address(graph, vendored, &scope_url, base_url);
+            rewrite(scope.imports, scopes.entry(key).or_default());
+        }
+    }
+
+    let scopes: Map<String, Value> = scopes
+        .into_iter()
+        .map(|(key, imports)| (key, Value::Object(imports)))
+        .collect();
+    serde_json::json!({
+      "imports": imports,
+      "scopes": scopes,
+    })
+}
+
+/// The address of `specifier` in the vendored import map: the vendored copy of
+/// a remote module or directory, or a path relative to the vendor directory.
+fn vendored_address(
+    graph: &ModuleGraph,
+    vendored: &BTreeMap<ModuleSpecifier, String>,
+    specifier: &ModuleSpecifier,
+    base_url: &ModuleSpecifier,
+) -> String {
+    if is_remote(specifier) {
+        if specifier.path().ends_with('/') {
+            return format!("./{}", mirrored_path(specifier));
+        }
+        return match vendored.get(&graph.resolve(specifier)) {
+            Some(path) => format!("./{}", path),
+            // Not part of the graph, so it wasn't vendored.
+            None => specifier.to_string(),
+        };
+    }
+    match base_url.make_relative(specifier) {
+        Some(relative) if specifier.scheme() == "file" => {
+            if relative.starts_with("../") {
+                relative
+            } else {
+                format!("./{}", relative)
+            }
+        }
+        _ => specifier.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_path_should_mirror_the_url() {
+        let path =
+            |s: &str, media_type| local_path(&ModuleSpecifier::parse(s).unwrap(), media_type);
+        assert_eq!(
+            path(
+                "https://deno.land/std@0.196.0/http/server.ts",
+                Some(MediaType::TypeScript)
+            ),
+            "deno.land/std@0.196.0/http/server.ts"
+        );
+        assert_eq!(
+            path("https://esm.sh/preact@10.16.0", Some(MediaType::JavaScript)),
+            "esm.sh/preact@10.16.0.js"
+        );
+        assert_eq!(
+            path("http://localhost:8000/", Some(MediaType::TypeScript)),
+            "localhost_8000/index.ts"
+        );
+        assert!(path("https://esm.sh/v128/preact.mjs?target=deno", None)
+            .starts_with("esm.sh/v128/preact_"));
+        assert_eq!(
+            path("https://example.com/data.txt", None),
+            "example.com/data.txt"
+        );
+        assert_eq!(
+            path("https://example.com/data.txt", Some(MediaType::JavaScript)),
+            "example.com/data.txt.js"
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use deno_core::{error::AnyError, futures::FutureExt, ModuleSpecifier};
+use deno_graph::{
+    source::{LoadFuture, LoadResponse, Loader},
+    ModuleGraph,
+};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc;
+
+use crate::utils::UniversalModuleLoader;
+
+use super::{
+    build_graph, bundle_module_graph, graph, lockfile, resolve_options, BundleEmit, BundleOptions,
+    BundleSession,
+};
+
+/// How long to wait for more file changes before rebuilding, so that saving
+/// several files at once triggers a single rebuild.
+const DEBOUNCE: Duration = Duration::from_millis(50);
+
+/// The outcome of one build of a watched entry point.
+pub enum WatchEvent {
+    Built {
+        emit: BundleEmit,
+        duration: Duration,
+    },
+    /// The build failed. `error` is a `GraphErrors` or `BundleDiagnostics` for
+    /// problems in the watched modules, or a `LockfileError` for changed remote
+    /// modules.
+    Failed { error: AnyError, duration: Duration },
+}
+
+/// Bundle `entry`, then bundle it again every time one of the local modules of
+/// its module graph changes, calling `on_event` with the outcome of each build.
+///
+/// Remote modules are loaded once, and modules whose source didn't change are
+/// not transpiled again. Modules which can't be loaded are watched too, so the
+/// entry is bundled again once they are fixed. This only returns when the file
+/// watcher stops.
+pub async fn watch(
+    entry: ModuleSpecifier,
+    options: &BundleOptions,
+    mut on_event: impl FnMut(WatchEvent),
+) -> Result<(), AnyError> {
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+        if let Ok(event) = res {
+            let _ = tx.send(event);
+        }
+    })?;
+    let mut session = BundleSession::default();
+    let mut loader = WatchLoader {
+        inner: UniversalModuleLoader::new(options.module_store.clone(), false)
+            .with_cache_policy(options.cache_policy.clone())
+            .with_fetch_client(options.fetch_client.clone()),
+        remote_responses: Default::default(),
+    };
+    let mut watched_dirs = HashSet::new();
+    loop {
+        let started = Instant::now();
+        let (result, files) = build(&entry, options, &session, &mut loader).await;
+        let duration = started.elapsed();
+        on_event(match result {
+            Ok(emit) => WatchEvent::Built { emit, duration },
+            Err(error) => WatchEvent::Failed { error, duration },
+        });
+        if session.is_bloated() {
+            session = BundleSession::default();
+        }
+
+        // Directories are watched rather than files, so that files replaced by
+        // a rename when saved, and files which don't exist yet, are noticed.
+        let dirs = watched_dirs_of(&files);
+        for dir in watched_dirs.difference(&dirs) {
+            let _ = watcher.unwatch(dir);
+        }
+        let added = dirs
+            .difference(&watched_dirs)
+            .filter(|dir| watcher.watch(dir, RecursiveMode::NonRecursive).is_ok())
+            .cloned()
+            .collect::<Vec<_>>();
+        watched_dirs.retain(|dir| dirs.contains(dir));
+        watched_dirs.extend(added);
+
+        loop {
+            let Some(event) = rx.recv().await else {
+                return Ok(());
+            };
+            if matches!(event.kind, EventKind::Remove(_)) {
+                // The watch of a removed directory is gone, so it is added
+                // again after the next build.
+                for path in &event.paths {
+                    watched_dirs.remove(path);
+                }
+            }
+            let is_change = matches!(
+                event.kind,
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+            );
+            // A path is relevant when it is a watched file or one of its
+            // ancestors, e.g. a missing directory which was created.
+            let is_relevant = event
+                .paths
+                .iter()
+                .any(|path| files.iter().any(|file| file.starts_with(path)));
+            if is_change && is_relevant {
+                break;
+            }
+        }
+        tokio::time::sleep(DEBOUNCE).await;
+        while rx.try_recv().is_ok() {}
+    }
+}
+
+/// The directories to watch for changes of `files`: the parent directory of
+/// each file, or its closest existing ancestor when it doesn't exist.
+fn watched_dirs_of(files: &HashSet<PathBuf>) -> HashSet<PathBuf> {
+    files
+        .iter()
+        .filter_map(|file| file.ancestors().skip(1).find(|dir| dir.is_dir()))
+        .map(Path::to_path_buf)
+        .collect()
+}
+
+/// Build the bundle once, returning it along with the local files of the
+/// module graph, which are watched even when the build fails.
+async fn build(
+    entry: &ModuleSpecifier,
+    options: &BundleOptions,
+    session: &BundleSession,
+    loader: &mut WatchLoader,
+) -> (Result<BundleEmit, AnyError>, HashSet<PathBuf>) {
+    let options = match resolve_options(options, entry) {
+        Ok(options) => options,
+        Err(err) => return (Err(err), local_files(entry, None)),
+    };
+    let graph = build_graph(vec![entry.clone()], &options, loader).await;
+    let files = local_files(entry, Some(&graph));
+    let result = graph::validate_graph(&graph, options.strict_json_imports)
+        .and_then(|()| match &options.lock_file {
+            Some(lock_file) => lockfile::check_lockfile(&graph, lock_file, options.lock_write),
+            None => Ok(()),
+        })
+        .and_then(|()| bundle_module_graph(&graph, &options, session));
+    (result, files)
+}
+
+fn local_files(entry: &ModuleSpecifier, graph: Option<&ModuleGraph>) -> HashSet<PathBuf> {
+    let specifiers = graph
+        .into_iter()
+        .flat_map(|graph| graph.specifiers().map(|(specifier, _)| specifier))
+        .chain(std::iter::once(entry));
+    specifiers
+        .filter(|specifier| specifier.scheme() == "file")
+        .filter_map(|specifier| specifier.to_file_path().ok())
+        .collect()
+}
+
+/// Loads local modules from disk on every build, and remote modules from the
+/// responses seen by previous builds, headers included.
+struct WatchLoader {
+    inner: UniversalModuleLoader,
+    remote_responses: Rc<RefCell<HashMap<ModuleSpecifier, RemoteResponse>>>,
+}
+
+#[derive(Clone)]
+enum RemoteResponse {
+    Module {
+        content: Arc<str>,
+        specifier: ModuleSpecifier,
+        maybe_headers: Option<HashMap<String, String>>,
+    },
+    Redirect(ModuleSpecifier),
+}
+
+impl From<RemoteResponse> for LoadResponse {
+    fn from(response: RemoteResponse) -> Self {
+        match response {
+            RemoteResponse::Module {
+                content,
+                specifier,
+                maybe_headers,
+            } => LoadResponse::Module {
+                content,
+                specifier,
+                maybe_headers,
+            },
+            RemoteResponse::Redirect(specifier) => LoadResponse::Redirect { specifier },
+        }
+    }
+}
+
+impl Loader for WatchLoader {
+    fn load(&mut self, specifier: &ModuleSpecifier, is_dynamic: bool) -> LoadFuture {
+        if let Some(response) = self.remote_responses.borrow().get(specifier) {
+            let response = response.clone().into();
+            return async move { Ok(Some(response)) }.boxed_local();
+        }
+        let future = self.inner.load(specifier, is_dynamic);
+        if specifier.scheme() == "file" {
+            return future;
+        }
+        let specifier = specifier.clone();
+        let remote_responses = self.remote_responses.clone();
+        async move {
+            let response = future.await?;
+            let remembered = match &response {
+                Some(LoadResponse::Module {
+                    content,
+                    specifier,
+                    maybe_headers,
+                }) => Some(RemoteResponse::Module {
+                    content: content.clone(),
+                    specifier: specifier.clone(),
+                    maybe_headers: maybe_headers.clone(),
+                }),
+                Some(LoadResponse::Redirect { specifier }) => {
+                    Some(RemoteResponse::Redirect(specifier.clone()))
+                }
+                _ => None,
+            };
+            if let Some(remembered) = remembered {
+                remote_responses.borrow_mut().insert(specifier, remembered);
+            }
+            Ok(response)
+        }
+        .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watched_dirs_should_be_the_closest_existing_ancestors() {
+        let tmp = std::env::temp_dir();
+        let files = HashSet::from([
+            tmp.join("app.ts"),
+            tmp.join("missing-dir-of-a-watch-test/routes/index.ts"),
+        ]);
+        assert_eq!(watched_dirs_of(&files), HashSet::from([tmp]));
+    }
+
+    #[tokio::test]
+    async fn remote_responses_should_be_replayed_with_their_headers() {
+        let mut loader = WatchLoader {
+            inner: UniversalModuleLoader::new(None, false),
+            remote_responses: Default::default(),
+        };
+        let specifier = ModuleSpecifier::parse("https://esm.sh/preact@10.16.0").unwrap();
+        let headers = HashMap::from([(
+            "content-type".to_string(),
+            "application/javascript".to_string(),
+        )]);
+        loader.remote_responses.borrow_mut().insert(
+            specifier.clone(),
+            RemoteResponse::Module {
+                content: "export const h = 1;".into(),
+                specifier: specifier.clone(),
+                maybe_headers: Some(headers.clone()),
+            },
+        );
+        let response = loader.load(&specifier, false).await.unwrap();
+        let Some(LoadResponse::Module { maybe_headers, .. }) = response else {
+            panic!("expected a module");
+        };
+        assert_eq!(maybe_headers, Some(headers));
+
+        let data =
+            ModuleSpecifier::parse("data:application/javascript,export%20default%201").unwrap();
+        loader.load(&data, false).await.unwrap();
+        assert!(loader.remote_responses.borrow().contains_key(&data));
+    }
+}
@@ -0,0 +1,122 @@
+use base64::{engine::general_purpose, Engine};
+use deno_core::{error::AnyError, serde_json, ModuleSpecifier};
+use std::{collections::HashMap, path::Path};
+
+/// Files imported as modules which aren't JavaScript, selected by extension.
+///
+/// Import attributes don't select the kind of an asset: the module graph fails
+/// any import with a `type` other than `"json"` before loading it, and loaders
+/// are given the specifier alone. Such imports are reported by
+/// `validate_graph` with a message pointing at the extension instead. A `.txt`
+/// file is a text asset imported without attributes, and a file with another
+/// extension can't be imported as one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssetKind {
+    /// Default exports the content as a string.
+    Text,
+    /// Default exports the content as a `Uint8Array`.
+    Bytes,
+    /// Default exports a compiled `WebAssembly.Module`, ready to instantiate.
+    Wasm,
+}
+
+impl AssetKind {
+    /// The kind of the asset at `specifier`, from the extension of its path
+    /// only, or `None` if it isn't an asset.
+    pub fn from_specifier(specifier: &ModuleSpecifier) -> Option<Self> {
+        let ext = Path::new(specifier.path())
+            .extension()?
+            .to_str()?
+            .to_lowercase();
+        match ext.as_str() {
+            "txt" | "text" | "html" | "htm" | "sql" | "md" | "css" | "csv" | "xml" | "svg"
+            | "graphql" | "gql" => Some(Self::Text),
+            "bin" | "png" | "jpg" | "jpeg" | "gif" | "webp" | "ico" => Some(Self::Bytes),
+            "wasm" => Some(Self::Wasm),
+            _ => None,
+        }
+    }
+}
+
+/// The headers of the synthesized module of an asset, which make the module
+/// graph treat it as JavaScript whatever its extension.
+pub fn asset_headers(specifier: &ModuleSpecifier) -> Option<HashMap<String, String>> {
+    AssetKind::from_specifier(specifier).map(|_| {
+        HashMap::from([(
+            "content-type".to_string(),
+            "application/javascript".to_string(),
+        )])
+    })
+}
+
+/// The JavaScript module exporting an asset of `kind` with content `bytes`.
+pub fn asset_module_source(kind: AssetKind, bytes: &[u8]) -> Result<String, AnyError> {
+    let code = match kind {
+        AssetKind::Text => {
+            let text = std::str::from_utf8(bytes)?;
+            format!("export default {};\n", serde_json::to_string(text)?)
+        }
+        AssetKind::Bytes => format!("export default {};\n", bytes_expr(bytes)),
+        AssetKind::Wasm => format!(
+            "export default new WebAssembly.Module({});\n",
+            bytes_expr(bytes)
+        ),
+    };
+    Ok(code)
+}
+
+/// A `Uint8Array` of `bytes`, decoded from base64 by an inlined function, as
+/// not every runtime the bundles target has `atob`.
+fn bytes_expr(bytes: &[u8]) -> String {
+    format!(
+        "{}(\"{}\")",
+        BASE64_DECODER,
+        general_purpose::STANDARD_NO_PAD.encode(bytes)
+    )
+}
+
+/// Decodes unpadded base64, accumulating 6 bits per character and storing a
+/// byte whenever 8 are available. Stored values are truncated to their low
+/// byte by the `Uint8Array`.
+const BASE64_DECODER: &str = "((s) => { \
+const a = \"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/\", \
+b = new Uint8Array((s.length * 3) >> 2); \
+for (let i = 0, j = 0, v = 0, n = 0; i < s.length; i++) { \
+v = (v << 6) | a.indexOf(s[i]); n += 6; if (n >= 8) b[j++] = v >> (n -= 8); \
+} return b; })";
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asset_kind_should_follow_the_extension() {
+        let kind = |s: &str| AssetKind::from_specifier(&ModuleSpecifier::parse(s).unwrap());
+        assert_eq!(kind("file:///app/query.SQL"), Some(AssetKind::Text));
+        assert_eq!(
+            kind("https://example.com/index.html?v=1"),
+            Some(AssetKind::Text)
+        );
+        assert_eq!(kind("file:///app/lib.wasm"), Some(AssetKind::Wasm));
+        assert_eq!(kind("file:///app/logo.png"), Some(AssetKind::Bytes));
+        assert_eq!(kind("file:///app/main.ts"), None);
+        // Neither the query nor the fragment select the kind.
+        assert_eq!(kind("file:///app/main.js?type=text"), None);
+        assert_eq!(kind("file:///app/notes.txt#bytes"), Some(AssetKind::Text));
+        assert_eq!(kind("file:///app/data.json"), None);
+    }
+
+    #[test]
+    fn asset_module_source_should_export_the_content() {
+        assert_eq!(
+            asset_module_source(AssetKind::Text, b"<p>\"hi\"</p>\n").unwrap(),
+            "export default \"<p>\\\"hi\\\"</p>\\n\";\n"
+        );
+        assert_eq!(
+            asset_module_source(AssetKind::Bytes, &[0, 97, 115, 109]).unwrap(),
+            format!("export default {}(\"AGFzbQ\");\n", BASE64_DECODER)
+        );
+        assert!(!BASE64_DECODER.contains("atob"));
+        assert!(asset_module_source(AssetKind::Text, &[0xff]).is_err());
+    }
+}
@@ -0,0 +1,222 @@
+use deno_core::{
+    anyhow::{bail, Context},
+    error::AnyError,
+    ModuleSpecifier,
+};
+use derive_builder::Builder;
+use reqwest::{header::HeaderMap, Certificate, Proxy, StatusCode};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use tokio::sync::OnceCell;
+
+/// How remote modules and npm packages are fetched.
+#[derive(Builder, Clone, Debug, PartialEq, Eq)]
+#[builder(default, pattern = "owned")]
+pub struct FetchOptions {
+    /// The time limit of every attempt of a request.
+    pub timeout: Duration,
+    /// How many times a request failing with a connection error, a timeout or
+    /// a 5xx status is retried.
+    pub retries: u32,
+    /// The delay before the first retry, doubled on every following one.
+    pub retry_delay: Duration,
+    /// The proxy `http:` requests go through. When unset, the `HTTP_PROXY`
+    /// environment variable is used.
+    pub http_proxy: Option<String>,
+    /// The proxy `https:` requests go through. When unset, the `HTTPS_PROXY`
+    /// environment variable is used.
+    pub https_proxy: Option<String>,
+    /// PEM files of root certificates trusted on top of the system ones, e.g.
+    /// the certificate of a corporate proxy.
+    pub root_certificates: Vec<PathBuf>,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(5000),
+            retries: 2,
+            retry_delay: Duration::from_millis(250),
+            http_proxy: None,
+            https_proxy: None,
+            root_certificates: Vec::new(),
+        }
+    }
+}
+
+impl FetchOptions {
+    /// The delay before the retry following `attempt`, starting at 0.
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.retry_delay.saturating_mul(1 << attempt.min(16))
+    }
+
+    /// The `http:` and `https:` proxies, when one of them is set. Setting a
+    /// proxy disables the ones of the environment, so the other one is read
+    /// from `env` here.
+    fn proxies(&self, env: impl Fn(&str) -> Option<String>) -> (Option<String>, Option<String>) {
+        if self.http_proxy.is_none() && self.https_proxy.is_none() {
+            return (None, None);
+        }
+        let from_env = |names: [&str; 2]| {
+            names
+                .into_iter()
+                .find_map(&env)
+                .filter(|value| !value.is_empty())
+        };
+        (
+            self.http_proxy
+                .clone()
+                .or_else(|| from_env(["HTTP_PROXY", "http_proxy"])),
+            self.https_proxy
+                .clone()
+                .or_else(|| from_env(["HTTPS_PROXY", "https_proxy"])),
+        )
+    }
+
+    fn build_client(&self) -> Result<reqwest::Client, AnyError> {
+        let mut builder = reqwest::Client::builder().timeout(self.timeout);
+        let (http_proxy, https_proxy) = self.proxies(|name| std::env::var(name).ok());
+        if let Some(proxy) = http_proxy {
+            builder = builder.proxy(Proxy::http(proxy)?);
+        }
+        if let Some(proxy) = https_proxy {
+            builder = builder.proxy(Proxy::https(proxy)?);
+        }
+        for path in &self.root_certificates {
+            let pem = std::fs::read(path)
+                .with_context(|| format!("Unable to read {}", path.display()))?;
+            let certificate = Certificate::from_pem(&pem)
+                .with_context(|| format!("Invalid certificate {}", path.display()))?;
+            builder = builder.add_root_certificate(certificate);
+        }
+        Ok(builder.build()?)
+    }
+}
+
+/// The HTTP client shared by the loaders. Cloning it shares its connection
+/// pool.
+///
+/// The default client is only built by its first request, which fails if it
+/// can't be built.
+#[derive(Clone, Debug, Default)]
+pub struct FetchClient {
+    client: Arc<OnceCell<reqwest::Client>>,
+    options: FetchOptions,
+}
+
+/// A response whose body was read.
+#[derive(Clone, Debug)]
+pub struct FetchResponse {
+    /// The URL of the response, after redirects.
+    pub url: ModuleSpecifier,
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub bytes: Vec<u8>,
+}
+
+impl FetchResponse {
+    /// Fail if the status is a client or server error.
+    pub fn error_for_status(self) -> Result<Self, AnyError> {
+        if self.status.is_client_error() || self.status.is_server_error() {
+            bail!("{} responded with {}.", self.url, self.status);
+        }
+        Ok(self)
+    }
+}
+
+impl FetchClient {
+    pub fn new(options: FetchOptions) -> Result<Self, AnyError> {
+        let client = options.build_client()?;
+        Ok(Self {
+            client: Arc::new(OnceCell::new_with(Some(client))),
+            options,
+        })
+    }
+
+    pub fn options(&self) -> &FetchOptions {
+        &self.options
+    }
+
+    /// Send a GET request for `url` and read its body, retrying with an
+    /// exponential backoff on connection errors, timeouts, including while
+    /// reading the body, and 5xx statuses. The response of the last attempt is
+    /// returned whatever its status.
+    pub async fn get(
+        &self,
+        url: &ModuleSpecifier,
+        headers: HeaderMap,
+    ) -> Result<FetchResponse, AnyError> {
+        let client = self
+            .client
+            .get_or_try_init(|| async { self.options.build_client() })
+            .await?;
+        let mut attempt = 0;
+        loop {
+            let result = match client
+                .get(url.clone())
+                .headers(headers.clone())
+                .send()
+                .await
+            {
+                Ok(res) => {
+                    let url = res.url().clone();
+                    let status = res.status();
+                    let headers = res.headers().clone();
+                    res.bytes().await.map(|bytes| FetchResponse {
+                        url,
+                        status,
+                        headers,
+                        bytes: bytes.to_vec(),
+                    })
+                }
+                Err(err) => Err(err),
+            };
+            let retry = match &result {
+                Ok(res) => res.status.is_server_error(),
+                Err(err) => err.is_connect() || err.is_timeout() || err.is_body(),
+            };
+            if !retry || attempt >= self.options.retries {
+                return Ok(result?);
+            }
+            tokio::time::sleep(self.options.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fetch_options_should_back_off_exponentially() {
+        let options = FetchOptionsBuilder::default()
+            .retry_delay(Duration::from_millis(100))
+            .build()
+            .unwrap();
+        assert_eq!(options.backoff(0), Duration::from_millis(100));
+        assert_eq!(options.backoff(1), Duration::from_millis(200));
+        assert_eq!(options.backoff(3), Duration::from_millis(800));
+        assert_eq!(options.timeout, Duration::from_millis(5000));
+    }
+
+    #[test]
+    fn fetch_options_should_keep_the_proxy_of_the_other_scheme() {
+        let env = |name: &str| match name {
+            "HTTP_PROXY" => Some("http://env-proxy:3128".to_string()),
+            "https_proxy" => Some("http://env-proxy:3129".to_string()),
+            _ => None,
+        };
+        let options = FetchOptionsBuilder::default()
+            .http_proxy(Some("http://proxy:8080".to_string()))
+            .build()
+            .unwrap();
+        assert_eq!(
+            options.proxies(env),
+            (
+                Some("http://proxy:8080".to_string()),
+                Some("http://env-proxy:3129".to_string())
+            )
+        );
+        assert_eq!(FetchOptions::default().proxies(env), (None, None));
+    }
+}
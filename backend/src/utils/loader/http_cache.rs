@@ -0,0 +1,148 @@
+use deno_core::{error::AnyError, ModuleSpecifier};
+use reqwest::{
+    header::{self, HeaderMap, HeaderValue},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::fetch::{FetchClient, FetchResponse};
+
+/// The HTTP response metadata stored along with a remote module, used to
+/// revalidate it.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpMetadata {
+    /// The URL the module was fetched from, after redirects.
+    pub url: String,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub cache_control: Option<String>,
+    /// When the module was fetched or last revalidated, in seconds since the
+    /// Unix epoch.
+    pub fetched_at: u64,
+}
+
+impl HttpMetadata {
+    fn from_response(res: &FetchResponse) -> Self {
+        let header = |name| {
+            res.headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+        Self {
+            url: res.url.to_string(),
+            content_type: header(header::CONTENT_TYPE),
+            etag: header(header::ETAG),
+            last_modified: header(header::LAST_MODIFIED),
+            cache_control: header(header::CACHE_CONTROL),
+            fetched_at: now(),
+        }
+    }
+
+    /// Whether the module can be used without revalidating it at `now`, per
+    /// its `Cache-Control` header. Modules without one are always revalidated.
+    pub fn is_fresh(&self, now: u64) -> bool {
+        let Some(cache_control) = &self.cache_control else {
+            return false;
+        };
+        let mut max_age = None;
+        let mut immutable = false;
+        for directive in cache_control.split(',') {
+            let directive = directive.trim().to_ascii_lowercase();
+            match directive.split_once('=') {
+                Some(("max-age", value)) => max_age = value.trim_matches('"').parse::<u64>().ok(),
+                Some(_) => {}
+                None if directive == "no-cache" || directive == "no-store" => return false,
+                None if directive == "immutable" => immutable = true,
+                None => {}
+            }
+        }
+        immutable
+            || max_age.map_or(false, |max_age| {
+                now < self.fetched_at.saturating_add(max_age)
+            })
+    }
+}
+
+/// The outcome of `fetch_remote`.
+#[derive(Clone, Debug)]
+pub enum RemoteSource {
+    Modified {
+        bytes: Vec<u8>,
+        metadata: HttpMetadata,
+    },
+    /// The cached module is still valid. Its metadata is refreshed with the
+    /// headers of the response.
+    NotModified(HttpMetadata),
+}
+
+/// Fetch a remote module. With the metadata of a cached copy, the request is
+/// conditional on its `ETag` and `Last-Modified` headers.
+pub async fn fetch_remote(
+    client: &FetchClient,
+    m: &ModuleSpecifier,
+    cached: Option<&HttpMetadata>,
+) -> Result<RemoteSource, AnyError> {
+    let mut headers = HeaderMap::new();
+    if let Some(cached) = cached {
+        if let Some(etag) = &cached.etag {
+            headers.insert(header::IF_NONE_MATCH, HeaderValue::from_str(etag)?);
+        }
+        if let Some(last_modified) = &cached.last_modified {
+            headers.insert(
+                header::IF_MODIFIED_SINCE,
+                HeaderValue::from_str(last_modified)?,
+            );
+        }
+    }
+    let res = client.get(m, headers).await?;
+    if let (Some(cached), StatusCode::NOT_MODIFIED) = (cached, res.status) {
+        let fresh = HttpMetadata::from_response(&res);
+        return Ok(RemoteSource::NotModified(HttpMetadata {
+            url: cached.url.clone(),
+            content_type: cached.content_type.clone(),
+            etag: fresh.etag.or_else(|| cached.etag.clone()),
+            last_modified: fresh.last_modified.or_else(|| cached.last_modified.clone()),
+            cache_control: fresh.cache_control.or_else(|| cached.cache_control.clone()),
+            fetched_at: fresh.fetched_at,
+        }));
+    }
+    // TODO: The HTML spec says to fail if the status is not
+    // 200-299, but `error_for_status()` fails if the status is
+    // 400-599.
+    let res = res.error_for_status()?;
+    let metadata = HttpMetadata::from_response(&res);
+    Ok(RemoteSource::Modified {
+        bytes: res.bytes,
+        metadata,
+    })
+}
+
+/// The current time in seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn http_metadata_should_honor_cache_control() {
+        let metadata = |cache_control: Option<&str>| HttpMetadata {
+            url: "https://deno.land/std@0.196.0/http/server.ts".to_string(),
+            cache_control: cache_control.map(str::to_string),
+            fetched_at: 1000,
+            ..Default::default()
+        };
+        assert!(!metadata(None).is_fresh(1000));
+        assert!(metadata(Some("public, max-age=60")).is_fresh(1059));
+        assert!(!metadata(Some("public, max-age=60")).is_fresh(1060));
+        assert!(metadata(Some("public, max-age=31536000, immutable")).is_fresh(u64::MAX));
+        assert!(!metadata(Some("no-cache, max-age=60")).is_fresh(1000));
+    }
+}
@@ -1,53 +1,124 @@
+pub mod asset;
+pub mod fetch;
+pub mod http_cache;
 pub mod universal_loader;
 
 use data_url::DataUrl;
 use deno_core::{anyhow::bail, error::AnyError, ModuleSpecifier};
-use std::{sync::Arc, time::Duration};
+use std::sync::Arc;
 
-use crate::utils::ModuleStore;
+use crate::utils::{ModuleResolver, ModuleStore};
+
+use fetch::FetchClient;
+use http_cache::{fetch_remote, RemoteSource};
 
 #[derive(Clone, Debug)]
 pub struct UniversalModuleLoader {
     store: Option<Arc<dyn ModuleStore>>,
+    resolver: ModuleResolver,
     #[allow(dead_code)]
     compile: bool,
+    cache_policy: CachePolicy,
+    client: FetchClient,
+}
+
+/// When the remote modules cached in the module store are used instead of
+/// fetching them again. Local files are always read.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum CachePolicy {
+    /// Never fetch remote modules, failing when one isn't cached. Stale modules
+    /// are used as is.
+    Only,
+    /// Fetch remote modules only when they aren't cached, or when their
+    /// `Cache-Control` header requires revalidating them.
+    #[default]
+    PreferCache,
+    /// Always fetch remote modules.
+    Reload,
+    /// Always fetch the remote modules whose specifier starts with one of the
+    /// prefixes, e.g. `https://deno.land/x/oak/`, and prefer the cache for the
+    /// others.
+    ReloadMatching(Vec<String>),
 }
 
-pub async fn get_source_code(m: &ModuleSpecifier) -> Result<String, AnyError> {
-    let code = match m.scheme() {
-        "http" | "https" => {
-            let client = reqwest::Client::builder()
-                .timeout(Duration::from_millis(5000))
-                .build()?;
-            let res = client.get(m.to_owned()).send().await?;
-            // TODO: The HTML spec says to fail if the status is not
-            // 200-299, but `error_for_status()` fails if the status is
-            // 400-599.
-            let res = res.error_for_status()?;
-            res.text().await?
+impl CachePolicy {
+    /// Whether the cached source of `m` may be used.
+    pub fn use_cache(&self, m: &ModuleSpecifier) -> bool {
+        match self {
+            CachePolicy::Only | CachePolicy::PreferCache => true,
+            CachePolicy::Reload => false,
+            CachePolicy::ReloadMatching(prefixes) => !prefixes
+                .iter()
+                .any(|prefix| m.as_str().starts_with(prefix.as_str())),
         }
+    }
+
+    /// Whether remote modules may be fetched.
+    pub fn allow_fetch(&self) -> bool {
+        *self != CachePolicy::Only
+    }
+}
+
+/// Whether `m` is fetched from the network, and so is subject to the
+/// `CachePolicy`.
+pub fn is_remote_specifier(m: &ModuleSpecifier) -> bool {
+    matches!(m.scheme(), "http" | "https")
+}
+
+pub async fn get_source_code(
+    client: &FetchClient,
+    m: &ModuleSpecifier,
+) -> Result<String, AnyError> {
+    match String::from_utf8(get_source_bytes(client, m).await?) {
+        Ok(code) => Ok(code),
+        Err(_) => bail!("The source of {} is not valid UTF-8.", m),
+    }
+}
+
+pub async fn get_source_bytes(
+    client: &FetchClient,
+    m: &ModuleSpecifier,
+) -> Result<Vec<u8>, AnyError> {
+    let bytes = match m.scheme() {
+        "http" | "https" => match fetch_remote(client, m, None).await? {
+            RemoteSource::Modified { bytes, .. } => bytes,
+            RemoteSource::NotModified(_) => bail!("Unexpected Not Modified response for {}.", m),
+        },
         "file" => {
             let path = match m.to_file_path() {
                 Ok(path) => path,
                 Err(_) => bail!("Invalid file URL."),
             };
-            tokio::fs::read_to_string(path).await?
+            tokio::fs::read(path).await?
         }
         "data" => {
             let url = match DataUrl::process(m.as_str()) {
                 Ok(url) => url,
                 Err(_) => bail!("Not a valid data URL."),
             };
-            let bytes = match url.decode_to_vec() {
+            match url.decode_to_vec() {
                 Ok((bytes, _)) => bytes,
                 Err(_) => bail!("Not a valid data URL."),
-            };
-            match String::from_utf8(bytes) {
-                Ok(s) => s,
-                Err(_) => bail!("Not a valid data URL code."),
             }
         }
         schema => bail!("Invalid schema {}", schema),
     };
-    Ok(code)
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_policy_should_reload_matching_prefixes() {
+        let m = |s: &str| ModuleSpecifier::parse(s).unwrap();
+        let policy = CachePolicy::ReloadMatching(vec!["https://deno.land/x/oak/".to_string()]);
+        assert!(!policy.use_cache(&m("https://deno.land/x/oak/mod.ts")));
+        assert!(policy.use_cache(&m("https://deno.land/std/http/server.ts")));
+        assert!(policy.allow_fetch());
+        assert!(CachePolicy::Only.use_cache(&m("https://deno.land/x/oak/mod.ts")));
+        assert!(!CachePolicy::Only.allow_fetch());
+        assert!(!CachePolicy::Reload.use_cache(&m("https://deno.land/x/oak/mod.ts")));
+    }
 }
@@ -2,7 +2,6 @@ use deno_core::anyhow::bail;
 use deno_core::error::AnyError;
 use deno_core::futures::FutureExt;
 use deno_core::resolve_import;
-use deno_core::FastString;
 use deno_core::ModuleLoader;
 use deno_core::ModuleSource;
 use deno_core::ModuleSourceFuture;
@@ -12,21 +11,32 @@ use deno_core::ModuleType;
 use deno_graph::source::{LoadFuture, LoadResponse, Loader};
 #[cfg(feature = "transpile")]
 use deno_transpiler::compile;
+use std::collections::HashMap;
 use std::path::PathBuf;
 use std::pin::Pin;
 use std::str;
 use std::sync::Arc;
 
+use crate::utils::npm::NpmResolver;
 use crate::utils::store::FsModuleStore;
-use crate::utils::to_static_str;
+use crate::utils::ModuleResolver;
 
-use super::{get_source_code, ModuleStore, UniversalModuleLoader};
+use super::{
+    asset::{asset_headers, asset_module_source, AssetKind},
+    fetch::FetchClient,
+    get_source_bytes,
+    http_cache::{fetch_remote, now, HttpMetadata, RemoteSource},
+    is_remote_specifier, CachePolicy, ModuleStore, UniversalModuleLoader,
+};
 
 impl Default for UniversalModuleLoader {
     fn default() -> Self {
         Self {
             store: Some(Arc::new(FsModuleStore::default())),
+            resolver: ModuleResolver::default(),
             compile: true,
+            cache_policy: CachePolicy::default(),
+            client: FetchClient::default(),
         }
     }
 }
@@ -35,28 +45,157 @@ impl UniversalModuleLoader {
     pub fn new(module_store: Option<Arc<dyn ModuleStore>>, compile: bool) -> Self {
         Self {
             store: module_store,
+            resolver: ModuleResolver::default(),
             compile,
+            cache_policy: CachePolicy::default(),
+            client: FetchClient::default(),
+        }
+    }
+
+    /// Use `resolver` to resolve the specifiers of imported modules.
+    pub fn with_resolver(mut self, resolver: ModuleResolver) -> Self {
+        self.resolver = resolver;
+        self
+    }
+
+    /// Use `cache_policy` to decide whether remote modules are fetched.
+    pub fn with_cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+        self.cache_policy = cache_policy;
+        self
+    }
+
+    /// Fetch remote modules with `client`.
+    pub fn with_fetch_client(mut self, client: FetchClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// The source of `m`, from the module store when the cache policy and the
+    /// `Cache-Control` header of the module allow it, fetching and storing it
+    /// otherwise. Stale modules are revalidated with a conditional request.
+    ///
+    /// Returns the HTTP metadata of remote modules along with their source,
+    /// which holds the URL they were found at after redirects.
+    pub async fn load_source(
+        &self,
+        m: &ModuleSpecifier,
+    ) -> Result<(String, Option<HttpMetadata>), AnyError> {
+        if !is_remote_specifier(m) {
+            let code = self.clone().get_and_update_source(m, false).await?;
+            return Ok((code, None));
+        }
+        let mut stale = None;
+        if let (true, Some(store)) = (self.cache_policy.use_cache(m), self.store.as_ref()) {
+            if let Ok(code) = store.get(m.as_str()).await {
+                let code = match String::from_utf8(code.into_vec()) {
+                    Ok(code) => code,
+                    Err(_) => bail!("The cached source of {} is not valid UTF-8.", m),
+                };
+                match store.get_metadata(m.as_str()).await? {
+                    Some(metadata)
+                        if self.cache_policy.allow_fetch() && !metadata.is_fresh(now()) =>
+                    {
+                        stale = Some((code, metadata))
+                    }
+                    metadata => return Ok((code, metadata)),
+                }
+            }
+        }
+        if !self.cache_policy.allow_fetch() {
+            bail!(
+                "{} is not in the module store, and the cache policy doesn't allow fetching it.",
+                m
+            );
+        }
+        let cached_metadata = stale.as_ref().map(|(_, metadata)| metadata);
+        match (fetch_remote(&self.client, m, cached_metadata).await?, stale) {
+            (RemoteSource::NotModified(metadata), Some((code, _))) => {
+                if let Some(store) = self.store.as_ref() {
+                    store.put_metadata(m.to_string(), &metadata).await?;
+                }
+                Ok((code, Some(metadata)))
+            }
+            (RemoteSource::NotModified(_), None) => {
+                bail!("Unexpected Not Modified response for {}.", m)
+            }
+            (RemoteSource::Modified { bytes, metadata }, _) => {
+                let code = self.update_source(m, bytes, Some(&metadata), false).await?;
+                Ok((code, Some(metadata)))
+            }
         }
     }
 
     pub async fn get_and_update_source(
         self,
         m: &ModuleSpecifier,
+        minify: bool,
+    ) -> Result<String, AnyError> {
+        if !is_remote_specifier(m) {
+            let bytes = get_source_bytes(&self.client, m).await?;
+            return self.update_source(m, bytes, None, minify).await;
+        }
+        match fetch_remote(&self.client, m, None).await? {
+            RemoteSource::Modified { bytes, metadata } => {
+                self.update_source(m, bytes, Some(&metadata), minify).await
+            }
+            RemoteSource::NotModified(_) => bail!("Unexpected Not Modified response for {}.", m),
+        }
+    }
+
+    /// Turn the fetched `bytes` of `m` into a module and store it, under the
+    /// URL it was found at as well when it was redirected.
+    async fn update_source(
+        &self,
+        m: &ModuleSpecifier,
+        bytes: Vec<u8>,
+        metadata: Option<&HttpMetadata>,
         #[allow(unused_variables)] minify: bool,
     ) -> Result<String, AnyError> {
+        let asset_kind = AssetKind::from_specifier(m);
+        #[allow(unused_variables)]
+        let is_json = matches!(get_module_type(m, metadata), ModuleType::Json);
         #[allow(unused_mut)]
-        let mut code = get_source_code(m).await?;
+        let mut code = match asset_kind {
+            Some(kind) => asset_module_source(kind, &bytes)?,
+            None => match String::from_utf8(bytes) {
+                Ok(code) => code,
+                Err(_) => bail!("The source of {} is not valid UTF-8.", m),
+            },
+        };
         #[cfg(feature = "transpile")]
-        if self.compile {
+        if self.compile && asset_kind.is_none() && !is_json {
             code = compile(m, code, minify)?;
         }
         if let Some(store) = self.store.as_ref() {
             store.put(m.to_string(), code.as_bytes()).await?;
+            if let Some(metadata) = metadata {
+                store.put_metadata(m.to_string(), metadata).await?;
+                if metadata.url != m.as_str() {
+                    store.put(metadata.url.clone(), code.as_bytes()).await?;
+                    store.put_metadata(metadata.url.clone(), metadata).await?;
+                }
+            }
         }
         Ok(code)
     }
 }
 
+/// The specifier a remote module was found at, after redirects.
+fn found_specifier(m: &ModuleSpecifier, metadata: Option<&HttpMetadata>) -> ModuleSpecifier {
+    metadata
+        .and_then(|metadata| ModuleSpecifier::parse(&metadata.url).ok())
+        .unwrap_or_else(|| m.clone())
+}
+
+/// Fail for a `node:` specifier, which is only loaded once the resolver maps it
+/// to a polyfill.
+fn check_node_specifier(m: &ModuleSpecifier) -> Result<(), AnyError> {
+    if m.scheme() == "node" {
+        bail!("\"{}\" must be mapped to a polyfill to be loaded.", m);
+    }
+    Ok(())
+}
+
 impl ModuleLoader for UniversalModuleLoader {
     fn resolve(
         &self,
@@ -64,7 +203,10 @@ impl ModuleLoader for UniversalModuleLoader {
         referrer: &str,
         kind: deno_core::ResolutionKind,
     ) -> Result<ModuleSpecifier, deno_core::anyhow::Error> {
-        Ok(resolve_import(specifier, referrer)?)
+        match ModuleSpecifier::parse(referrer) {
+            Ok(referrer) => self.resolver.resolve(specifier, &referrer),
+            Err(_) => Ok(resolve_import(specifier, referrer)?),
+        }
     }
 
     fn load(
@@ -78,27 +220,35 @@ impl ModuleLoader for UniversalModuleLoader {
 
         let loader = self.clone();
         async move {
-            let module_type = get_module_type(&m)?;
-            if let Some(store) = loader.store.as_ref() {
-                if let Ok(code) = store.get(&string_specifier).await {
-                    let code = std::str::from_utf8(&code).unwrap();
+            if let Some(npm) = loader.resolver.npm() {
+                if NpmResolver::is_npm_specifier(&m) {
+                    let module = npm.load(&m).await?;
+                    let module_type = if module.content_type == "application/json" {
+                        ModuleType::Json
+                    } else {
+                        ModuleType::JavaScript
+                    };
                     return Ok(ModuleSource {
-                        code: FastString::Static(to_static_str(code)),
+                        code: module.code.into(),
                         module_type,
-                        module_url_specified: FastString::Static(to_static_str(&string_specifier)),
-                        module_url_found: Some(FastString::Static(to_static_str(
-                            &string_specifier,
-                        ))),
+                        module_url_specified: string_specifier.into(),
+                        module_url_found: Some(module.specifier.to_string().into()),
                     });
                 }
             }
-            let code = loader.get_and_update_source(&m, false).await?;
+            check_node_specifier(&m)?;
+            let (code, metadata) = loader.load_source(&m).await?;
+            // deno_core checks the type of the module against the `type`
+            // attribute of the import, so that JSON modules are only imported
+            // with `{ type: "json" }`.
+            let module_type = get_module_type(&m, metadata.as_ref());
+            let found = found_specifier(&m, metadata.as_ref());
 
             Ok(ModuleSource {
-                code: FastString::Static(to_static_str(&code)),
+                code: code.into(),
                 module_type,
-                module_url_specified: FastString::Static(to_static_str(&string_specifier)),
-                module_url_found: Some(FastString::Static(to_static_str(&string_specifier))),
+                module_url_specified: string_specifier.clone().into(),
+                module_url_found: Some(found.to_string().into()),
             })
         }
         .boxed_local()
@@ -111,32 +261,136 @@ impl Loader for UniversalModuleLoader {
         let loader = self.clone();
         let m = specifier.clone();
         async move {
-            let code = loader.get_and_update_source(&m, false).await?;
+            check_node_specifier(&m)?;
+            let (code, metadata) = loader.load_source(&m).await?;
+            let found = found_specifier(&m, metadata.as_ref());
+            if found != m {
+                return Ok(Some(LoadResponse::Redirect { specifier: found }));
+            }
             Ok(Some(LoadResponse::Module {
                 content: code.into(),
+                maybe_headers: module_headers(&m, metadata.as_ref()),
                 specifier: m,
-                maybe_headers: None,
             }))
         }
         .boxed_local()
     }
 }
 
-fn get_module_type(m: &ModuleSpecifier) -> Result<ModuleType, AnyError> {
-    let path = if let Ok(path) = m.to_file_path() {
-        path
+/// The headers of `m` for the module graph, which picks the media type of a
+/// module from its `Content-Type` before its extension: those of an asset, or
+/// the `Content-Type` of a remote module, so that a remote module without an
+/// extension isn't taken for JavaScript.
+fn module_headers(
+    m: &ModuleSpecifier,
+    metadata: Option<&HttpMetadata>,
+) -> Option<HashMap<String, String>> {
+    asset_headers(m).or_else(|| {
+        let content_type = metadata?.content_type.clone()?;
+        Some(HashMap::from([("content-type".to_string(), content_type)]))
+    })
+}
+
+/// The type of `m`: JSON when the `Content-Type` header of a remote module or
+/// the extension says so, JavaScript otherwise.
+fn get_module_type(m: &ModuleSpecifier, metadata: Option<&HttpMetadata>) -> ModuleType {
+    let is_json_content = metadata
+        .and_then(|metadata| metadata.content_type.as_deref())
+        .and_then(|value| value.parse::<mime::Mime>().ok())
+        .map_or(false, |mime| {
+            mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON)
+        });
+    let path = m.to_file_path().unwrap_or_else(|_| PathBuf::from(m.path()));
+    let is_json_file = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map_or(false, |ext| ext.eq_ignore_ascii_case("json"));
+    if is_json_content || is_json_file {
+        ModuleType::Json
     } else {
-        PathBuf::from(m.path())
-    };
-    match path.extension() {
-        Some(ext) => {
-            let lowercase_str = ext.to_str().map(|s| s.to_lowercase());
-            match lowercase_str.as_deref() {
-                Some("json") => Ok(ModuleType::Json),
-                None => bail!("Unknown extension"),
-                _ => Ok(ModuleType::JavaScript),
-            }
-        }
-        None => bail!("Unknown media type {:?}", path),
+        ModuleType::JavaScript
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn module_type_should_follow_the_content_type_or_the_extension() {
+        let m = ModuleSpecifier::parse("https://example.com/data").unwrap();
+        let metadata = |content_type: &str| HttpMetadata {
+            url: m.to_string(),
+            content_type: Some(content_type.to_string()),
+            ..Default::default()
+        };
+        let json = metadata("application/json; charset=utf-8");
+        assert!(matches!(get_module_type(&m, Some(&json)), ModuleType::Json));
+        let manifest = metadata("application/manifest+json");
+        assert!(matches!(
+            get_module_type(&m, Some(&manifest)),
+            ModuleType::Json
+        ));
+        let js = metadata("application/javascript");
+        assert!(matches!(
+            get_module_type(&m, Some(&js)),
+            ModuleType::JavaScript
+        ));
+        let raw = ModuleSpecifier::parse("https://example.com/data.JSON").unwrap();
+        let text = metadata("text/plain");
+        assert!(matches!(
+            get_module_type(&raw, Some(&text)),
+            ModuleType::Json
+        ));
+    }
+
+    #[cfg(feature = "bundle")]
+    #[tokio::test]
+    async fn unmapped_node_specifiers_should_fail_like_at_runtime() {
+        let m = ModuleSpecifier::parse("node:fs").unwrap();
+        let mut loader = UniversalModuleLoader::new(None, false);
+        let err = Loader::load(&mut loader, &m, false).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "\"node:fs\" must be mapped to a polyfill to be loaded."
+        );
+    }
+
+    #[cfg(feature = "bundle")]
+    #[tokio::test]
+    async fn remote_modules_should_be_loaded_with_their_content_type() {
+        let store = Arc::new(FsModuleStore::new("/tmp/deno_universal_loader_test"));
+        let m = ModuleSpecifier::parse("https://example.com/mod").unwrap();
+        let metadata = HttpMetadata {
+            url: m.to_string(),
+            content_type: Some("application/typescript; charset=utf-8".to_string()),
+            ..Default::default()
+        };
+        store
+            .put(m.to_string(), b"export const x: number = 1;")
+            .await
+            .unwrap();
+        store.put_metadata(m.to_string(), &metadata).await.unwrap();
+        let mut loader =
+            UniversalModuleLoader::new(Some(store), false).with_cache_policy(CachePolicy::Only);
+        let response = Loader::load(&mut loader, &m, false).await.unwrap();
+        let Some(LoadResponse::Module { maybe_headers, .. }) = response else {
+            panic!("{} should be loaded as a module", m);
+        };
+        assert_eq!(
+            maybe_headers.unwrap()["content-type"],
+            "application/typescript; charset=utf-8"
+        );
+
+        let asset = ModuleSpecifier::parse("https://example.com/notes.txt").unwrap();
+        let metadata = HttpMetadata {
+            url: asset.to_string(),
+            content_type: Some("text/plain".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            module_headers(&asset, Some(&metadata)).unwrap()["content-type"],
+            "application/javascript"
+        );
     }
 }
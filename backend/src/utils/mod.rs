@@ -1,21 +1,41 @@
 mod compressible;
 mod fs_util;
 mod loader;
+pub mod node;
+pub mod npm;
+mod resolver;
 mod store;
 
 pub use compressible::*;
 pub use fs_util::*;
 pub use loader::*;
+pub use resolver::*;
 pub use store::*;
 
 use async_trait::async_trait;
 use deno_core::error::AnyError;
 use std::{fmt, slice::from_raw_parts, str::from_utf8_unchecked};
 
+use http_cache::HttpMetadata;
+
 #[async_trait]
 pub trait ModuleStore: fmt::Debug + Send + Sync {
     async fn get(&self, specifier: &str) -> Result<Box<[u8]>, AnyError>;
     async fn put(&self, specifier: String, code: &[u8]) -> Result<(), AnyError>;
+
+    /// The HTTP metadata of a stored remote module. Stores which don't keep
+    /// metadata make the loader use their modules without revalidating them.
+    async fn get_metadata(&self, _specifier: &str) -> Result<Option<HttpMetadata>, AnyError> {
+        Ok(None)
+    }
+
+    async fn put_metadata(
+        &self,
+        _specifier: String,
+        _metadata: &HttpMetadata,
+    ) -> Result<(), AnyError> {
+        Ok(())
+    }
 }
 
 pub fn to_static_str(s: &str) -> &'static str {
@@ -0,0 +1,171 @@
+use deno_core::{anyhow::bail, error::AnyError, ModuleSpecifier};
+use std::collections::BTreeMap;
+
+/// The built-in modules of Node.js.
+pub const NODE_BUILTINS: &[&str] = &[
+    "assert",
+    "assert/strict",
+    "async_hooks",
+    "buffer",
+    "child_process",
+    "cluster",
+    "console",
+    "constants",
+    "crypto",
+    "dgram",
+    "diagnostics_channel",
+    "dns",
+    "dns/promises",
+    "domain",
+    "events",
+    "fs",
+    "fs/promises",
+    "http",
+    "http2",
+    "https",
+    "inspector",
+    "module",
+    "net",
+    "os",
+    "path",
+    "path/posix",
+    "path/win32",
+    "perf_hooks",
+    "process",
+    "punycode",
+    "querystring",
+    "readline",
+    "readline/promises",
+    "repl",
+    "stream",
+    "stream/consumers",
+    "stream/promises",
+    "stream/web",
+    "string_decoder",
+    "sys",
+    "timers",
+    "timers/promises",
+    "tls",
+    "tty",
+    "url",
+    "util",
+    "util/types",
+    "v8",
+    "vm",
+    "wasi",
+    "worker_threads",
+    "zlib",
+];
+
+/// How a Node.js built-in module is provided.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeBuiltin {
+    /// `node:` imports resolve to this module, which is bundled in.
+    Polyfill(ModuleSpecifier),
+    /// `node:` imports are left as is, for the runtime to provide the module.
+    External,
+}
+
+/// Maps the `node:` specifiers to the modules providing them. Importing a
+/// built-in without a mapping fails.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NodeBuiltins(BTreeMap<String, NodeBuiltin>);
+
+impl NodeBuiltins {
+    /// Every built-in provided by the runtime.
+    pub fn external() -> Self {
+        NODE_BUILTINS
+            .iter()
+            .fold(Self::default(), |builtins, name| {
+                builtins.with(name, NodeBuiltin::External)
+            })
+    }
+
+    /// Provide the built-in `name`, e.g. `buffer` or `fs/promises`, with
+    /// `builtin`.
+    pub fn with(mut self, name: &str, builtin: NodeBuiltin) -> Self {
+        self.0.insert(name.to_string(), builtin);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&NodeBuiltin> {
+        self.0.get(name)
+    }
+
+    /// The names of the built-ins provided by the runtime.
+    pub fn external_names(&self) -> impl Iterator<Item = &str> {
+        self.0
+            .iter()
+            .filter(|(_, builtin)| **builtin == NodeBuiltin::External)
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Resolve the `node:` specifier of a built-in, e.g. `node:buffer`.
+    pub fn resolve(&self, specifier: &str) -> Result<ModuleSpecifier, AnyError> {
+        let name = specifier.strip_prefix("node:").unwrap_or(specifier);
+        match self.0.get(name) {
+            Some(NodeBuiltin::Polyfill(polyfill)) => Ok(polyfill.clone()),
+            Some(NodeBuiltin::External) => Ok(ModuleSpecifier::parse(&format!("node:{}", name))?),
+            None => {
+                let supported: Vec<&str> = self.0.keys().map(String::as_str).collect();
+                let kind = if NODE_BUILTINS.contains(&name) {
+                    "is not supported"
+                } else {
+                    "is not a Node.js built-in module"
+                };
+                if supported.is_empty() {
+                    bail!(
+                        "\"node:{}\" {}. No Node.js built-in modules are configured.",
+                        name,
+                        kind
+                    )
+                }
+                bail!(
+                    "\"node:{}\" {}. Supported built-ins: {}.",
+                    name,
+                    kind,
+                    supported.join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// Whether a bare specifier imported by an npm package, such as `events` or
+/// `fs/promises`, refers to a built-in module.
+pub fn is_node_builtin(specifier: &str) -> bool {
+    NODE_BUILTINS.contains(&specifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn node_builtins_should_resolve_polyfills_and_externals() {
+        let polyfill =
+            ModuleSpecifier::parse("https://deno.land/std@0.177.0/node/buffer.ts").unwrap();
+        let builtins = NodeBuiltins::default()
+            .with("buffer", NodeBuiltin::Polyfill(polyfill.clone()))
+            .with("events", NodeBuiltin::External);
+        assert_eq!(builtins.resolve("node:buffer").unwrap(), polyfill);
+        assert_eq!(builtins.resolve("events").unwrap().as_str(), "node:events");
+        assert_eq!(
+            builtins.external_names().collect::<Vec<_>>(),
+            vec!["events"]
+        );
+        assert_eq!(
+            builtins.resolve("node:fs").unwrap_err().to_string(),
+            "\"node:fs\" is not supported. Supported built-ins: buffer, events."
+        );
+        assert!(builtins
+            .resolve("node:left-pad")
+            .unwrap_err()
+            .to_string()
+            .contains("is not a Node.js built-in module"));
+    }
+}
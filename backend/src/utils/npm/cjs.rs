@@ -0,0 +1,397 @@
+use deno_core::serde_json;
+use std::path::Path;
+
+/// Names which can't be exported, being reserved words or names declared by
+/// the wrapper of `cjs_to_esm`.
+const UNEXPORTABLE_NAMES: &[&str] = &[
+    "default",
+    "__esModule",
+    "__cjsModule",
+    "__modules",
+    "module",
+    "require",
+    "break",
+    "case",
+    "catch",
+    "class",
+    "const",
+    "continue",
+    "debugger",
+    "delete",
+    "do",
+    "else",
+    "enum",
+    "export",
+    "extends",
+    "false",
+    "finally",
+    "for",
+    "function",
+    "if",
+    "import",
+    "in",
+    "instanceof",
+    "new",
+    "null",
+    "return",
+    "super",
+    "switch",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "typeof",
+    "var",
+    "void",
+    "while",
+    "with",
+    "yield",
+    "let",
+    "static",
+    "implements",
+    "interface",
+    "package",
+    "private",
+    "protected",
+    "public",
+    "await",
+    "arguments",
+    "eval",
+];
+
+/// Whether a file of a package is an ES module, from its extension, the `type`
+/// of its `package.json` and, for plain `.js` files of CommonJS packages, the
+/// presence of `import` or `export` statements.
+pub fn is_esm(path: &str, source: &str, package_type: Option<&str>) -> bool {
+    match Path::new(path).extension().and_then(|s| s.to_str()) {
+        Some("mjs") => true,
+        Some("cjs") => false,
+        _ => package_type == Some("module") || has_esm_syntax(source),
+    }
+}
+
+fn has_esm_syntax(source: &str) -> bool {
+    source.lines().any(|line| {
+        let line = line.trim_start();
+        [
+            "import ", "import{", "import\"", "import'", "export ", "export{", "export*",
+        ]
+        .iter()
+        .any(|prefix| line.starts_with(prefix))
+    })
+}
+
+/// Wrap a CommonJS module into an ES module default exporting `module.exports`.
+///
+/// The modules passed to `require` with a string literal at the top level are
+/// imported up front, and the properties assigned to `exports` are also
+/// exported by name, so ES modules can `import { name }` from the module.
+/// Requiring an ES module returns its namespace. Requiring a module which
+/// wasn't imported up front throws when it runs.
+pub fn cjs_to_esm(source: &str) -> String {
+    let requires = required_specifiers(source);
+    let mut code = String::new();
+    for (i, specifier) in requires.iter().enumerate() {
+        code.push_str(&format!(
+            "import * as __require{} from {};\n",
+            i,
+            serde_json::to_string(specifier).unwrap()
+        ));
+    }
+    code.push_str("const __modules = {");
+    for (i, specifier) in requires.iter().enumerate() {
+        code.push_str(&format!(
+            "\n  {}: __require{},",
+            serde_json::to_string(specifier).unwrap(),
+            i
+        ));
+    }
+    code.push_str(
+        "\n};
+const require = (specifier) => {
+  const m = __modules[specifier];
+  if (m === undefined) {
+    throw new Error(
+      `Cannot find module \"${specifier}\": only the modules required at the top level are bundled.`,
+    );
+  }
+  return m.__cjsModule ? m.default : m;
+};
+const module = { exports: {} };
+(function (module, exports, require, global) {
+",
+    );
+    code.push_str(source);
+    code.push_str(
+        "
+}).call(module.exports, module, module.exports, require, globalThis);
+export default module.exports;
+export const __cjsModule = true;
+",
+    );
+    let names = exported_names(source);
+    if !names.is_empty() {
+        code.push_str(&format!(
+            "export const {{ {} }} = module.exports;\n",
+            names.join(", ")
+        ));
+    }
+    code
+}
+
+/// The string literals passed to `require` at the top level, in order of
+/// appearance.
+///
+/// Requires in the body of a function, or in a `try` or `catch` block, may
+/// never run, as with optional dependencies, so they aren't hoisted into
+/// imports, which would fail the whole module when one is missing. The bodies
+/// of functions called right away, `(function () { ... })()`, are top level.
+fn required_specifiers(source: &str) -> Vec<String> {
+    let mut specifiers: Vec<String> = Vec::new();
+    // Whether each open brace is in a block which may never run.
+    let mut blocks: Vec<bool> = Vec::new();
+    let bytes = source.as_bytes();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = source[i..].find('\n').map_or(bytes.len(), |end| i + end);
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = source[i + 2..]
+                    .find("*/")
+                    .map_or(bytes.len(), |end| i + 2 + end + 2);
+                continue;
+            }
+            quote @ (b'"' | b'\'' | b'`') => {
+                i = string_end(bytes, i, quote);
+                continue;
+            }
+            b'{' => {
+                let lazy = blocks.last() == Some(&true) || is_lazy_block(&source[..i]);
+                blocks.push(lazy);
+            }
+            b'}' => {
+                blocks.pop();
+            }
+            b'r' if blocks.last() != Some(&true) && source[i..].starts_with("require(") => {
+                let preceded_by_ident = source[..i]
+                    .chars()
+                    .next_back()
+                    .map_or(false, |c| is_ident_char(c) || c == '.');
+                if !preceded_by_ident {
+                    if let Some(specifier) = required_literal(&source[i + "require(".len()..]) {
+                        if !specifiers.iter().any(|s| s == specifier) {
+                            specifiers.push(specifier.to_string());
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    specifiers
+}
+
+/// The string literal starting `args`, the arguments of a `require` call, if
+/// it is the only one.
+fn required_literal(args: &str) -> Option<&str> {
+    let args = args.trim_start();
+    let quote = args.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let end = args[1..].find(quote)?;
+    let specifier = &args[1..1 + end];
+    let is_call = args[2 + end..].trim_start().starts_with(')');
+    (is_call && !specifier.contains('\\')).then_some(specifier)
+}
+
+/// The index after the string literal starting at `start` with `quote`. Other
+/// than template literals, strings end at the end of the line, which limits the
+/// damage of a quote in a regular expression.
+fn string_end(bytes: &[u8], start: usize, quote: u8) -> usize {
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 2,
+            b'\n' if quote != b'`' => return i + 1,
+            c if c == quote => return i + 1,
+            _ => i += 1,
+        }
+    }
+    bytes.len()
+}
+
+/// Whether the block opened right after `before` may never run: the body of a
+/// function not called right away, or a `try` or `catch` block.
+fn is_lazy_block(before: &str) -> bool {
+    let before = before.trim_end();
+    if let Some(params) = before.strip_suffix("=>") {
+        let params = params.trim_end();
+        let head = match params.strip_suffix(')') {
+            Some(params) => match matching_paren(params) {
+                Some(open) => &params[..open],
+                None => return true,
+            },
+            None => params.trim_end_matches(is_ident_char),
+        };
+        let head = head.trim_end();
+        return !is_called_right_away(head.strip_suffix("async").unwrap_or(head));
+    }
+    let Some(params) = before.strip_suffix(')') else {
+        return matches!(last_word(before), "try" | "catch");
+    };
+    let Some(open) = matching_paren(params) else {
+        return false;
+    };
+    let head = params[..open].trim_end();
+    match last_word(head) {
+        "if" | "for" | "while" | "switch" | "with" => false,
+        "catch" => true,
+        "function" => !is_called_right_away(&head[..head.len() - "function".len()]),
+        name => {
+            // A named function, or else a method.
+            let head = head[..head.len() - name.len()].trim_end();
+            match head.strip_suffix("function") {
+                Some(head) => !is_called_right_away(head),
+                None => true,
+            }
+        }
+    }
+}
+
+/// Whether a function starting right after `head` is called right away, being
+/// wrapped in parentheses or negated.
+fn is_called_right_away(head: &str) -> bool {
+    head.trim_end().ends_with(['(', '!'])
+}
+
+/// The index of the parenthesis opening the one closed right after `before`.
+fn matching_paren(before: &str) -> Option<usize> {
+    let mut depth = 1;
+    for (i, c) in before.char_indices().rev() {
+        match c {
+            ')' => depth += 1,
+            '(' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn last_word(s: &str) -> &str {
+    &s[s.trim_end_matches(is_ident_char).len()..]
+}
+
+/// The names assigned with `exports.name =`, `module.exports.name =` or
+/// `Object.defineProperty(exports, "name", ...)`.
+fn exported_names(source: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut add = |name: &str| {
+        let valid = !name.is_empty()
+            && !name.starts_with(|c: char| c.is_ascii_digit())
+            && !name.starts_with("__require")
+            && !UNEXPORTABLE_NAMES.contains(&name)
+            && !names.iter().any(|n| n == name);
+        if valid {
+            names.push(name.to_string());
+        }
+    };
+    let mut rest = source;
+    while let Some(i) = rest.find("exports.") {
+        let preceded_by_ident = rest[..i].chars().next_back().map_or(false, is_ident_char);
+        rest = &rest[i + "exports.".len()..];
+        let len = rest.find(|c: char| !is_ident_char(c)).unwrap_or(rest.len());
+        let after = rest[len..].trim_start();
+        let is_assignment = after.starts_with('=') && !after.starts_with("==");
+        if !preceded_by_ident && is_assignment {
+            add(&rest[..len]);
+        }
+    }
+    let mut rest = source;
+    while let Some(i) = rest.find("Object.defineProperty(exports,") {
+        rest = rest[i + "Object.defineProperty(exports,".len()..].trim_start();
+        if let Some(quote) = rest.chars().next().filter(|c| *c == '"' || *c == '\'') {
+            if let Some(end) = rest[1..].find(quote) {
+                add(&rest[1..1 + end]);
+            }
+        }
+    }
+    names
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '$'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn is_esm_should_detect_module_syntax() {
+        assert!(is_esm("index.mjs", "module.exports = 1;", None));
+        assert!(!is_esm("index.cjs", "export default 1;", Some("module")));
+        assert!(is_esm("index.js", "module.exports = 1;", Some("module")));
+        assert!(is_esm("index.js", "import { h } from \"preact\";", None));
+        assert!(!is_esm("index.js", "const x = require(\"./x\");", None));
+    }
+
+    #[test]
+    fn cjs_to_esm_should_import_requires_and_export_names() {
+        let source = r#"
+const util = require("./util");
+const x = foo.require('ignored');
+exports.format = util.format;
+module.exports.parse = function () {};
+Object.defineProperty(exports, "__esModule", { value: true });
+Object.defineProperty(exports, 'version', { value: "1.0.0" });
+if (exports.format === undefined) {}
+"#;
+        assert_eq!(required_specifiers(source), vec!["./util"]);
+        assert_eq!(exported_names(source), vec!["format", "parse", "version"]);
+
+        let code = cjs_to_esm(source);
+        assert!(code.starts_with("import * as __require0 from \"./util\";\n"));
+        assert!(code.ends_with("export const { format, parse, version } = module.exports;\n"));
+    }
+
+    #[test]
+    fn cjs_to_esm_should_only_import_top_level_requires() {
+        let source = r#"
+const a = require("./a");
+if (process.env.NODE_ENV === "production") {
+  module.exports = require("./prod");
+} else {
+  module.exports = require("./dev");
+}
+(function () {
+  require("./iife");
+})();
+try {
+  require("optional");
+} catch {
+  require("fallback");
+}
+function lazy() {
+  return require("./lazy");
+}
+const arrow = () => { require("./arrow"); };
+const obj = { method() { require("./method"); }, b: require("./b") };
+// require("./comment")
+const text = "require('./string')";
+"#;
+        assert_eq!(
+            required_specifiers(source),
+            vec!["./a", "./prod", "./dev", "./iife", "./b"]
+        );
+        let code = cjs_to_esm(source);
+        assert!(!code.contains("from \"optional\""));
+        assert!(code.contains("only the modules required at the top level are bundled"));
+    }
+}
@@ -0,0 +1,135 @@
+use deno_core::serde_json::Value;
+
+/// The conditions of `exports` targets honored, in order of preference.
+const CONDITIONS: &[&str] = &[
+    "deno", "worker", "browser", "import", "module", "default", "require",
+];
+
+/// Resolve `sub_path` of a package, such as `hooks` for `preact/hooks`, to the
+/// path of the file within the package, following the `exports` field of its
+/// `package.json`, or `module` and `main` for packages without `exports`.
+///
+/// Returns `None` when `exports` doesn't export the subpath.
+pub fn resolve_package_entry(package_json: &Value, sub_path: Option<&str>) -> Option<String> {
+    let sub_path = sub_path
+        .map(|s| s.trim_matches('/'))
+        .filter(|s| !s.is_empty());
+    let path = match package_json.get("exports") {
+        Some(exports) if !exports.is_null() => {
+            let key = match sub_path {
+                Some(sub_path) => format!("./{}", sub_path),
+                None => ".".to_string(),
+            };
+            resolve_exports(exports, &key)?
+        }
+        _ => match sub_path {
+            Some(sub_path) => sub_path.to_string(),
+            None => ["module", "main"]
+                .iter()
+                .find_map(|field| package_json.get(field).and_then(Value::as_str))
+                .unwrap_or("index.js")
+                .to_string(),
+        },
+    };
+    Some(path.trim_start_matches("./").to_string())
+}
+
+fn resolve_exports(exports: &Value, key: &str) -> Option<String> {
+    let Value::Object(map) = exports else {
+        return (key == ".")
+            .then(|| resolve_target(exports, None))
+            .flatten();
+    };
+    if !map.keys().all(|k| k.starts_with('.')) {
+        // Conditions of the main entry point.
+        return (key == ".")
+            .then(|| resolve_target(exports, None))
+            .flatten();
+    }
+    if let Some(target) = map.get(key) {
+        return resolve_target(target, None);
+    }
+    // The subpath pattern with the longest prefix matching `key`.
+    map.iter()
+        .filter_map(|(pattern, target)| {
+            let (prefix, suffix) = pattern.split_once('*')?;
+            let star = key.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            Some((prefix.len(), star, target))
+        })
+        .max_by_key(|(len, _, _)| *len)
+        .and_then(|(_, star, target)| resolve_target(target, Some(star)))
+}
+
+fn resolve_target(target: &Value, star: Option<&str>) -> Option<String> {
+    match target {
+        Value::String(path) => Some(match star {
+            Some(star) => path.replace('*', star),
+            None => path.clone(),
+        }),
+        Value::Array(targets) => targets.iter().find_map(|t| resolve_target(t, star)),
+        Value::Object(conditions) => CONDITIONS
+            .iter()
+            .filter_map(|condition| conditions.get(*condition))
+            .find_map(|t| resolve_target(t, star)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use deno_core::serde_json::json;
+
+    #[test]
+    fn resolve_package_entry_should_follow_exports() {
+        let package_json = json!({
+          "main": "dist/preact.js",
+          "exports": {
+            ".": {
+              "types": "./src/index.d.ts",
+              "require": "./dist/preact.js",
+              "import": "./dist/preact.mjs"
+            },
+            "./hooks": {
+              "browser": "./hooks/dist/hooks.module.js",
+              "require": "./hooks/dist/hooks.js"
+            },
+            "./compat/*": "./compat/src/*.js",
+            "./internal/*": null,
+            "./package.json": "./package.json"
+          }
+        });
+        let resolve = |sub_path| resolve_package_entry(&package_json, sub_path);
+        assert_eq!(resolve(None).as_deref(), Some("dist/preact.mjs"));
+        assert_eq!(
+            resolve(Some("hooks")).as_deref(),
+            Some("hooks/dist/hooks.module.js")
+        );
+        assert_eq!(
+            resolve(Some("compat/server")).as_deref(),
+            Some("compat/src/server.js")
+        );
+        assert_eq!(resolve(Some("internal/x")), None);
+        assert_eq!(resolve(Some("dist/preact.js")), None);
+    }
+
+    #[test]
+    fn resolve_package_entry_should_fall_back_to_main() {
+        assert_eq!(
+            resolve_package_entry(&json!({ "exports": "./index.mjs" }), None).as_deref(),
+            Some("index.mjs")
+        );
+        assert_eq!(
+            resolve_package_entry(&json!({ "main": "./lib/index" }), None).as_deref(),
+            Some("lib/index")
+        );
+        assert_eq!(
+            resolve_package_entry(&json!({ "main": "lib/index.js" }), Some("lib/util")).as_deref(),
+            Some("lib/util")
+        );
+        assert_eq!(
+            resolve_package_entry(&json!({}), None).as_deref(),
+            Some("index.js")
+        );
+    }
+}
@@ -0,0 +1,582 @@
+mod cjs;
+mod exports;
+mod registry;
+
+pub use cjs::*;
+pub use exports::*;
+pub use registry::*;
+
+#[cfg(feature = "bundle")]
+use deno_core::futures::FutureExt;
+use deno_core::{
+    anyhow::{anyhow, bail, Context},
+    error::AnyError,
+    serde_json::{self, Value},
+    ModuleSpecifier,
+};
+#[cfg(feature = "bundle")]
+use deno_graph::source::{LoadFuture, LoadResponse, Loader};
+use flate2::read::GzDecoder;
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Component, Path},
+    sync::{Arc, Mutex},
+};
+
+use crate::utils::{fetch::FetchClient, is_remote_specifier, CachePolicy, ModuleStore};
+
+/// The prefix of the specifiers of the files of unpacked packages, such as
+/// `npm:/preact@10.16.0/dist/preact.module.js`.
+const PACKAGE_URL_PREFIX: &str = "npm:/";
+
+/// The prefix of the keys of unpacked packages in the `ModuleStore`.
+const STORE_KEY_PREFIX: &str = "npm-package:";
+
+/// The prefix of the keys of packuments in the `ModuleStore`.
+const PACKUMENT_KEY_PREFIX: &str = "npm-packument:";
+
+/// A `npm:` specifier, e.g. `npm:@preact/signals@^1.2/dist/signals.mjs`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NpmSpecifier {
+    pub name: String,
+    /// A version range or dist-tag, the `latest` tag when unset.
+    pub version_req: Option<String>,
+    pub sub_path: Option<String>,
+}
+
+impl NpmSpecifier {
+    pub fn parse(specifier: &str) -> Result<Self, AnyError> {
+        let Some(rest) = specifier
+            .strip_prefix("npm:")
+            .filter(|s| !s.starts_with('/'))
+        else {
+            bail!("Invalid npm specifier \"{}\".", specifier);
+        };
+        let rest = percent_decode(rest);
+        // The name of a scoped package contains a slash.
+        let name_end = match rest.strip_prefix('@') {
+            Some(scoped) => scoped.find('/').map(|i| i + 2).and_then(|start| {
+                rest[start..]
+                    .find('/')
+                    .map(|i| start + i)
+                    .or(Some(rest.len()))
+            }),
+            None => rest.find('/').or(Some(rest.len())),
+        };
+        let Some(name_end) = name_end else {
+            bail!("Invalid npm specifier \"{}\".", specifier);
+        };
+        let (name_and_version, sub_path) = rest.split_at(name_end);
+        let (name, version_req) = match name_and_version[1..].rfind('@') {
+            Some(i) => (
+                &name_and_version[..i + 1],
+                Some(name_and_version[i + 2..].to_string()),
+            ),
+            None => (name_and_version, None),
+        };
+        if name.is_empty() || name.ends_with('/') {
+            bail!("Invalid npm specifier \"{}\".", specifier);
+        }
+        let sub_path = sub_path.trim_start_matches('/');
+        Ok(Self {
+            name: name.to_string(),
+            version_req: version_req.filter(|s| !s.is_empty()),
+            sub_path: (!sub_path.is_empty()).then(|| sub_path.to_string()),
+        })
+    }
+}
+
+/// The specifier of the file at `path` in the package `name@version`.
+pub fn package_file_url(
+    name: &str,
+    version: &str,
+    path: &str,
+) -> Result<ModuleSpecifier, AnyError> {
+    Ok(ModuleSpecifier::parse(&format!(
+        "{}{}@{}/{}",
+        PACKAGE_URL_PREFIX, name, version, path
+    ))?)
+}
+
+/// The `name@version` of the package of a file of an unpacked package, e.g.
+/// `@preact/signals@1.2.0` for `npm:/@preact/signals@1.2.0/dist/signals.mjs`.
+pub fn package_id(specifier: &ModuleSpecifier) -> Option<String> {
+    let (name, version, _) = parse_package_file_url(specifier)?;
+    Some(format!("{}@{}", name, version))
+}
+
+/// Split the specifier of a file of an unpacked package into the name and
+/// version of the package and the path of the file.
+fn parse_package_file_url(specifier: &ModuleSpecifier) -> Option<(String, String, String)> {
+    let rest = specifier.as_str().strip_prefix(PACKAGE_URL_PREFIX)?;
+    let rest = percent_decode(rest);
+    let scope_len = if rest.starts_with('@') {
+        rest.find('/')? + 1
+    } else {
+        0
+    };
+    let end = scope_len + rest[scope_len..].find('/')?;
+    let (name, version) = rest[..end]
+        .rsplit_once('@')
+        .filter(|(name, _)| !name.is_empty())?;
+    Some((
+        name.to_string(),
+        version.to_string(),
+        rest[end + 1..].to_string(),
+    ))
+}
+
+fn percent_decode(s: &str) -> String {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut input = s.bytes();
+    while let Some(b) = input.next() {
+        if b == b'%' {
+            let hex = input.clone().take(2).collect::<Vec<_>>();
+            if let Some(decoded) = std::str::from_utf8(&hex)
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+            {
+                bytes.push(decoded);
+                input.nth(1);
+                continue;
+            }
+        }
+        bytes.push(b);
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+/// A module loaded from an npm package.
+pub struct NpmModule {
+    /// The specifier of the file the module was loaded from, which differs from
+    /// the requested specifier when it was resolved to an entry point.
+    pub specifier: ModuleSpecifier,
+    pub code: String,
+    pub content_type: &'static str,
+}
+
+#[derive(Debug)]
+struct NpmPackage {
+    package_json: Value,
+    files: HashMap<String, Arc<[u8]>>,
+}
+
+/// Resolves `npm:` specifiers to the files of packages downloaded from an npm
+/// registry.
+///
+/// Unpacked packages are cached in the `ModuleStore`. The same resolver is
+/// shared by the `ModuleResolver`, which resolves the bare imports of package
+/// files with the dependencies of their package, and the module loaders.
+#[derive(Debug)]
+pub struct NpmResolver {
+    registry: NpmRegistry,
+    store: Option<Arc<dyn ModuleStore>>,
+    client: FetchClient,
+    cache_policy: CachePolicy,
+    packuments: Mutex<HashMap<String, Arc<Packument>>>,
+    packages: Mutex<HashMap<String, Arc<NpmPackage>>>,
+}
+
+impl NpmResolver {
+    pub fn new(registry: NpmRegistry, store: Option<Arc<dyn ModuleStore>>) -> Self {
+        Self {
+            registry,
+            store,
+            client: FetchClient::default(),
+            cache_policy: CachePolicy::default(),
+            packuments: Default::default(),
+            packages: Default::default(),
+        }
+    }
+
+    /// Download packages with `client`.
+    pub fn with_fetch_client(mut self, client: FetchClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Use `cache_policy` to decide whether the packuments of the module store
+    /// are used, as `npm:<name>` specifiers. Packages are immutable, so stored
+    /// ones are always used, but `CachePolicy::Only` never downloads them.
+    pub fn with_cache_policy(mut self, cache_policy: CachePolicy) -> Self {
+        self.cache_policy = cache_policy;
+        self
+    }
+
+    pub fn is_npm_specifier(specifier: &ModuleSpecifier) -> bool {
+        specifier.scheme() == "npm"
+    }
+
+    /// Resolve a bare specifier imported by a file of a loaded package, using
+    /// the version range of the `dependencies` of the package.
+    pub fn resolve_package_import(
+        &self,
+        specifier: &str,
+        referrer: &ModuleSpecifier,
+    ) -> Option<ModuleSpecifier> {
+        let is_bare = !specifier.starts_with('.')
+            && !specifier.starts_with('/')
+            && ModuleSpecifier::parse(specifier).is_err();
+        if !is_bare {
+            return None;
+        }
+        let (referrer_name, referrer_version, _) = parse_package_file_url(referrer)?;
+        let package = self
+            .packages
+            .lock()
+            .unwrap()
+            .get(&format!("{}@{}", referrer_name, referrer_version))?
+            .clone();
+        let import = NpmSpecifier::parse(&format!("npm:{}", specifier)).ok()?;
+        let range = ["dependencies", "peerDependencies", "optionalDependencies"]
+            .iter()
+            .find_map(|field| package.package_json.get(field)?.get(&import.name)?.as_str());
+        let resolved = match range {
+            // An aliased dependency, e.g. `"string-width": "npm:string-width@^4"`.
+            Some(alias) if alias.starts_with("npm:") => format!(
+                "{}/{}",
+                alias,
+                import.sub_path.as_deref().unwrap_or_default()
+            ),
+            Some(range) => format!(
+                "npm:{}@{}/{}",
+                import.name,
+                range,
+                import.sub_path.as_deref().unwrap_or_default()
+            ),
+            None => format!("npm:{}", specifier),
+        };
+        ModuleSpecifier::parse(resolved.trim_end_matches('/')).ok()
+    }
+
+    /// Load a `npm:` specifier. A package specifier is resolved to the entry
+    /// point of the package, and a file specifier without extension is resolved
+    /// like Node.js does.
+    pub async fn load(&self, specifier: &ModuleSpecifier) -> Result<NpmModule, AnyError> {
+        let (name, version, path) = match parse_package_file_url(specifier) {
+            Some((name, version, path)) => (name, version, Ok(path)),
+            None => {
+                let npm_specifier = NpmSpecifier::parse(specifier.as_str())?;
+                let packument = self.packument(&npm_specifier.name).await?;
+                let version = resolve_version(&packument, npm_specifier.version_req.as_deref())
+                    .with_context(|| format!("Unable to resolve {}", specifier))?;
+                (npm_specifier.name, version, Err(npm_specifier.sub_path))
+            }
+        };
+        let package = self.package(&name, &version).await?;
+        let path = match path {
+            Ok(path) => path,
+            Err(sub_path) => resolve_package_entry(&package.package_json, sub_path.as_deref())
+                .ok_or_else(|| anyhow!("{} is not exported by {}@{}", specifier, name, version))?,
+        };
+        let path = probe_file(&package, &path)
+            .ok_or_else(|| anyhow!("Module not found \"{}\".", specifier))?;
+        let source = std::str::from_utf8(&package.files[&path])
+            .with_context(|| format!("{} is not valid UTF-8", path))?;
+        let package_type = package.package_json.get("type").and_then(Value::as_str);
+        let (code, content_type) = if path.ends_with(".json") {
+            (source.to_string(), "application/json")
+        } else if is_esm(&path, source, package_type) {
+            (source.to_string(), "application/javascript")
+        } else {
+            (cjs_to_esm(source), "application/javascript")
+        };
+        Ok(NpmModule {
+            specifier: package_file_url(&name, &version, &path)?,
+            code,
+            content_type,
+        })
+    }
+
+    async fn packument(&self, name: &str) -> Result<Arc<Packument>, AnyError> {
+        if let Some(packument) = self.packuments.lock().unwrap().get(name) {
+            return Ok(packument.clone());
+        }
+        let key = format!("{}{}", PACKUMENT_KEY_PREFIX, name);
+        let specifier = ModuleSpecifier::parse(&format!("npm:{}", name))?;
+        let stored = match (self.cache_policy.use_cache(&specifier), self.store.as_ref()) {
+            (true, Some(store)) => store
+                .get(&key)
+                .await
+                .ok()
+                .and_then(|bytes| serde_json::from_slice(&bytes).ok()),
+            _ => None,
+        };
+        let packument = match stored {
+            Some(packument) => Arc::new(packument),
+            None => {
+                self.check_fetch(name)?;
+                let packument = self.registry.packument(&self.client, name).await?;
+                if let Some(store) = self.store.as_ref() {
+                    store.put(key, &serde_json::to_vec(&packument)?).await?;
+                }
+                Arc::new(packument)
+            }
+        };
+        self.packuments
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), packument.clone());
+        Ok(packument)
+    }
+
+    /// The unpacked package `name@version`, from memory, the `ModuleStore`, or
+    /// downloaded from the registry.
+    async fn package(&self, name: &str, version: &str) -> Result<Arc<NpmPackage>, AnyError> {
+        let id = format!("{}@{}", name, version);
+        if let Some(package) = self.packages.lock().unwrap().get(&id) {
+            return Ok(package.clone());
+        }
+        let files = match self.stored_files(&id).await {
+            Some(files) => files,
+            None => {
+                let packument = self.packument(name).await?;
+                let Some(packument_version) = packument.versions.get(version) else {
+                    bail!("npm package {} not found", id);
+                };
+                self.check_fetch(&id)?;
+                let tarball = self
+                    .registry
+                    .tarball(&self.client, packument_version)
+                    .await?;
+                let files = unpack_tarball(&tarball)
+                    .with_context(|| format!("Unable to unpack npm package {}", id))?;
+                self.store_files(&id, &files).await?;
+                files
+            }
+        };
+        let package_json = match files.get("package.json") {
+            Some(bytes) => serde_json::from_slice(bytes)
+                .with_context(|| format!("Invalid package.json in npm package {}", id))?,
+            None => Value::Object(Default::default()),
+        };
+        let package = Arc::new(NpmPackage {
+            package_json,
+            files,
+        });
+        self.packages.lock().unwrap().insert(id, package.clone());
+        Ok(package)
+    }
+
+    /// Fail when the cache policy doesn't allow downloading `package` from a
+    /// remote registry.
+    fn check_fetch(&self, package: &str) -> Result<(), AnyError> {
+        if !self.cache_policy.allow_fetch() && is_remote_specifier(self.registry.url()) {
+            bail!(
+                "npm package {} is not in the module store, and the cache policy doesn't allow \
+                 fetching it.",
+                package
+            );
+        }
+        Ok(())
+    }
+
+    async fn stored_files(&self, id: &str) -> Option<HashMap<String, Arc<[u8]>>> {
+        let store = self.store.as_ref()?;
+        let index = store
+            .get(&format!("{}{}", STORE_KEY_PREFIX, id))
+            .await
+            .ok()?;
+        let paths: Vec<String> = serde_json::from_slice(&index).ok()?;
+        let mut files = HashMap::new();
+        for path in paths {
+            let key = format!("{}{}/{}", STORE_KEY_PREFIX, id, path);
+            let bytes = store.get(&key).await.ok()?;
+            files.insert(path, Arc::from(bytes));
+        }
+        Some(files)
+    }
+
+    async fn store_files(
+        &self,
+        id: &str,
+        files: &HashMap<String, Arc<[u8]>>,
+    ) -> Result<(), AnyError> {
+        let Some(store) = self.store.as_ref() else {
+            return Ok(());
+        };
+        for (path, bytes) in files {
+            store
+                .put(format!("{}{}/{}", STORE_KEY_PREFIX, id, path), bytes)
+                .await?;
+        }
+        // The index is written last, so a partially stored package is ignored.
+        let paths: Vec<&String> = files.keys().collect();
+        store
+            .put(
+                format!("{}{}", STORE_KEY_PREFIX, id),
+                &serde_json::to_vec(&paths)?,
+            )
+            .await
+    }
+}
+
+/// The files of a package tarball, keyed by their path in the package.
+fn unpack_tarball(tarball: &[u8]) -> Result<HashMap<String, Arc<[u8]>>, AnyError> {
+    let mut archive = tar::Archive::new(GzDecoder::new(tarball));
+    let mut files = HashMap::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        // Every file is in a top level directory, usually `package/`.
+        let path = entry.path()?.into_owned();
+        let mut components = path.components();
+        components.next();
+        let path = components.as_path();
+        if path
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_)))
+        {
+            continue;
+        }
+        let Some(path) = path.to_str().map(|s| s.replace('\\', "/")) else {
+            continue;
+        };
+        let mut bytes = Vec::new();
+        entry.read_to_end(&mut bytes)?;
+        files.insert(path, Arc::from(bytes));
+    }
+    Ok(files)
+}
+
+/// Find the file of a package `path` refers to, trying the extensions and
+/// index files Node.js tries.
+fn probe_file(package: &NpmPackage, path: &str) -> Option<String> {
+    let path = path.trim_start_matches("./").trim_end_matches('/');
+    let candidates = [
+        path.to_string(),
+        format!("{}.js", path),
+        format!("{}.mjs", path),
+        format!("{}.cjs", path),
+        format!("{}.json", path),
+        format!("{}/index.js", path),
+        format!("{}/index.mjs", path),
+        format!("{}/index.cjs", path),
+        format!("{}/index.json", path),
+    ];
+    candidates.into_iter().find(|candidate| {
+        !candidate.starts_with('/')
+            && Path::new(candidate).extension().is_some()
+            && package.files.contains_key(candidate)
+    })
+}
+
+/// A graph loader which loads `npm:` specifiers with an `NpmResolver`.
+#[cfg(feature = "bundle")]
+pub struct NpmLoader<'a> {
+    pub inner: &'a mut dyn Loader,
+    pub npm: Arc<NpmResolver>,
+}
+
+#[cfg(feature = "bundle")]
+impl Loader for NpmLoader<'_> {
+    fn load(&mut self, specifier: &ModuleSpecifier, is_dynamic: bool) -> LoadFuture {
+        if !NpmResolver::is_npm_specifier(specifier) {
+            return self.inner.load(specifier, is_dynamic);
+        }
+        let npm = self.npm.clone();
+        let specifier = specifier.clone();
+        async move {
+            let module = npm.load(&specifier).await?;
+            if module.specifier != specifier {
+                return Ok(Some(LoadResponse::Redirect {
+                    specifier: module.specifier,
+                }));
+            }
+            Ok(Some(LoadResponse::Module {
+                content: module.code.into(),
+                specifier,
+                maybe_headers: Some(HashMap::from([(
+                    "content-type".to_string(),
+                    module.content_type.to_string(),
+                )])),
+            }))
+        }
+        .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn npm_specifier_should_parse() {
+        let parse = |s| NpmSpecifier::parse(s).unwrap();
+        assert_eq!(
+            parse("npm:preact@^10.16/hooks"),
+            NpmSpecifier {
+                name: "preact".to_string(),
+                version_req: Some("^10.16".to_string()),
+                sub_path: Some("hooks".to_string()),
+            }
+        );
+        assert_eq!(
+            parse("npm:@preact/signals@1.2.0"),
+            NpmSpecifier {
+                name: "@preact/signals".to_string(),
+                version_req: Some("1.2.0".to_string()),
+                sub_path: None,
+            }
+        );
+        assert_eq!(
+            parse("npm:@preact/signals/dist/signals.mjs"),
+            NpmSpecifier {
+                name: "@preact/signals".to_string(),
+                version_req: None,
+                sub_path: Some("dist/signals.mjs".to_string()),
+            }
+        );
+        assert!(NpmSpecifier::parse("npm:/preact@10.16.0/hooks").is_err());
+        assert!(NpmSpecifier::parse("npm:@preact").is_err());
+    }
+
+    #[test]
+    fn package_file_url_should_round_trip() {
+        let url = package_file_url("@preact/signals", "1.2.0", "dist/signals.mjs").unwrap();
+        assert_eq!(url.as_str(), "npm:/@preact/signals@1.2.0/dist/signals.mjs");
+        assert_eq!(
+            parse_package_file_url(&url),
+            Some((
+                "@preact/signals".to_string(),
+                "1.2.0".to_string(),
+                "dist/signals.mjs".to_string()
+            ))
+        );
+        assert_eq!(package_id(&url).as_deref(), Some("@preact/signals@1.2.0"));
+        let relative = url.join("../package.json").unwrap();
+        assert_eq!(relative.as_str(), "npm:/@preact/signals@1.2.0/package.json");
+    }
+
+    #[tokio::test]
+    async fn packuments_should_follow_the_cache_policy() {
+        let store = Arc::new(crate::utils::FsModuleStore::new("/tmp/deno_npm_store"));
+        let packument = serde_json::json!({
+            "dist-tags": { "latest": "10.16.0" },
+            "versions": {
+                "10.16.0": {
+                    "dist": { "tarball": "https://registry.invalid/preact/-/preact-10.16.0.tgz" }
+                }
+            }
+        });
+        store
+            .put(
+                format!("{}preact", PACKUMENT_KEY_PREFIX),
+                packument.to_string().as_bytes(),
+            )
+            .await
+            .unwrap();
+        let registry =
+            NpmRegistry::new(ModuleSpecifier::parse("https://registry.invalid/").unwrap());
+        let npm = NpmResolver::new(registry, Some(store)).with_cache_policy(CachePolicy::Only);
+        let stored = npm.packument("preact").await.unwrap();
+        assert!(stored.versions.contains_key("10.16.0"));
+        let err = npm.packument("react").await.unwrap_err();
+        assert!(err.to_string().contains("cache policy"), "{}", err);
+        let err = npm.package("preact", "10.16.0").await.unwrap_err();
+        assert!(err.to_string().contains("cache policy"), "{}", err);
+    }
+}
@@ -0,0 +1,366 @@
+use base64::{engine::general_purpose, Engine};
+use deno_core::{
+    anyhow::{anyhow, bail, Context},
+    error::AnyError,
+    serde_json, ModuleSpecifier,
+};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::collections::HashMap;
+
+use crate::utils::fetch::FetchClient;
+
+const DEFAULT_REGISTRY: &str = "https://registry.npmjs.org/";
+
+/// An npm-compatible registry packages are downloaded from.
+///
+/// A `file:` URL points at a directory of tarballs named like `npm pack` does,
+/// e.g. `preact-10.16.0.tgz`, or `preact-signals-1.2.0.tgz` for the scoped
+/// `@preact/signals`, which stands in for a registry in tests and offline
+/// setups.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NpmRegistry {
+    url: ModuleSpecifier,
+}
+
+impl Default for NpmRegistry {
+    fn default() -> Self {
+        Self::new(ModuleSpecifier::parse(DEFAULT_REGISTRY).unwrap())
+    }
+}
+
+/// The versions of a package published to the registry.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Packument {
+    #[serde(rename = "dist-tags", default)]
+    pub dist_tags: HashMap<String, String>,
+    pub versions: HashMap<String, PackumentVersion>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PackumentVersion {
+    pub dist: PackumentDist,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PackumentDist {
+    pub tarball: String,
+    /// The Subresource Integrity hashes of the tarball, such as `sha512-...`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<String>,
+    /// The hex SHA-1 of the tarball, which older packages have instead of
+    /// `integrity`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shasum: Option<String>,
+}
+
+impl PackumentDist {
+    /// Check `tarball` against the strongest hash of `integrity`, or against
+    /// `shasum` when `integrity` has none. Tarballs without either, such as
+    /// those of a registry directory, are accepted.
+    pub fn check_integrity(&self, tarball: &[u8]) -> Result<(), AnyError> {
+        let hashes: Vec<(&str, &str)> = self
+            .integrity
+            .iter()
+            .flat_map(|integrity| integrity.split_whitespace())
+            .filter_map(|hash| hash.split('?').next()?.split_once('-'))
+            .collect();
+        let strongest = ["sha512", "sha384", "sha256", "sha1"]
+            .into_iter()
+            .find(|algorithm| hashes.iter().any(|(a, _)| a == algorithm));
+        let (expected, actual) = match (strongest, &self.shasum) {
+            (Some(algorithm), _) => {
+                let digest = match algorithm {
+                    "sha512" => Sha512::digest(tarball).to_vec(),
+                    "sha384" => Sha384::digest(tarball).to_vec(),
+                    "sha256" => Sha256::digest(tarball).to_vec(),
+                    _ => Sha1::digest(tarball).to_vec(),
+                };
+                let actual = general_purpose::STANDARD.encode(digest);
+                if hashes.contains(&(algorithm, actual.as_str())) {
+                    return Ok(());
+                }
+                (
+                    self.integrity.clone().unwrap_or_default(),
+                    format!("{}-{}", algorithm, actual),
+                )
+            }
+            (None, Some(shasum)) => {
+                let actual = format!("{:x}", Sha1::digest(tarball));
+                if shasum.eq_ignore_ascii_case(&actual) {
+                    return Ok(());
+                }
+                (shasum.clone(), actual)
+            }
+            (None, None) => return Ok(()),
+        };
+        bail!(
+            "Integrity check failed for npm tarball {}: expected {}, got {}.",
+            self.tarball,
+            expected,
+            actual
+        )
+    }
+}
+
+impl NpmRegistry {
+    pub fn new(mut url: ModuleSpecifier) -> Self {
+        if !url.path().ends_with('/') {
+            url.set_path(&format!("{}/", url.path()));
+        }
+        Self { url }
+    }
+
+    pub fn url(&self) -> &ModuleSpecifier {
+        &self.url
+    }
+
+    pub async fn packument(&self, client: &FetchClient, name: &str) -> Result<Packument, AnyError> {
+        match self.url.scheme() {
+            "http" | "https" => {
+                let url = self.url.join(&name.replace('/', "%2f"))?;
+                let res = client
+                    .get(&url, Default::default())
+                    .await?
+                    .error_for_status()?;
+                let packument = serde_json::from_slice(&res.bytes)
+                    .with_context(|| format!("Invalid packument for npm package {}", name))?;
+                Ok(packument)
+            }
+            "file" => self.directory_packument(name),
+            scheme => bail!("Unsupported npm registry scheme {}", scheme),
+        }
+    }
+
+    pub async fn tarball(
+        &self,
+        client: &FetchClient,
+        version: &PackumentVersion,
+    ) -> Result<Vec<u8>, AnyError> {
+        let url = ModuleSpecifier::parse(&version.dist.tarball)?;
+        let tarball = match url.scheme() {
+            "http" | "https" => {
+                let res = client
+                    .get(&url, Default::default())
+                    .await?
+                    .error_for_status()?;
+                res.bytes
+            }
+            "file" => {
+                let path = url
+                    .to_file_path()
+                    .map_err(|_| anyhow!("Invalid tarball URL {}", url))?;
+                tokio::fs::read(path).await?
+            }
+            scheme => bail!("Unsupported tarball scheme {}", scheme),
+        };
+        version.dist.check_integrity(&tarball)?;
+        Ok(tarball)
+    }
+
+    /// List the tarballs of `name` in a registry directory.
+    fn directory_packument(&self, name: &str) -> Result<Packument, AnyError> {
+        let dir = self
+            .url
+            .to_file_path()
+            .map_err(|_| anyhow!("Invalid npm registry {}", self.url))?;
+        let mut versions = HashMap::new();
+        let entries = std::fs::read_dir(&dir)
+            .with_context(|| format!("npm package {} not found in {}", name, self.url))?;
+        for entry in entries {
+            let path = entry?.path();
+            let Some(file_name) = path.file_name().and_then(|s| s.to_str()) else {
+                continue;
+            };
+            let version = tarball_version(name, file_name);
+            if let (Some(version), Ok(tarball)) = (version, ModuleSpecifier::from_file_path(&path))
+            {
+                versions.insert(
+                    version.to_string(),
+                    PackumentVersion {
+                        dist: PackumentDist {
+                            tarball: tarball.to_string(),
+                            integrity: None,
+                            shasum: None,
+                        },
+                    },
+                );
+            }
+        }
+        if versions.is_empty() {
+            bail!("npm package {} not found in {}", name, self.url);
+        }
+        Ok(Packument {
+            dist_tags: HashMap::new(),
+            versions,
+        })
+    }
+}
+
+/// The version of the tarball named `file_name` if it is one of `name`, as
+/// named by `npm pack`: `@scope/name@1.0.0` is packed as `scope-name-1.0.0.tgz`.
+fn tarball_version<'a>(name: &str, file_name: &'a str) -> Option<&'a str> {
+    let prefix = format!("{}-", name.trim_start_matches('@').replace('/', "-"));
+    file_name
+        .strip_prefix(&prefix)
+        .and_then(|s| s.strip_suffix(".tgz"))
+        .filter(|s| Version::parse(s).is_ok())
+}
+
+/// Pick the highest version of the packument matching the npm version range or
+/// dist-tag `req`, defaulting to the `latest` tag.
+pub fn resolve_version(packument: &Packument, req: Option<&str>) -> Result<String, AnyError> {
+    let req = req
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .unwrap_or("latest");
+    if let Some(version) = packument.dist_tags.get(req) {
+        return Ok(version.clone());
+    }
+    let ranges = if req == "latest" {
+        vec![VersionReq::STAR]
+    } else {
+        parse_range(req)?
+    };
+    packument
+        .versions
+        .keys()
+        .filter_map(|v| Version::parse(v).ok())
+        .filter(|v| ranges.iter().any(|range| range.matches(v)))
+        .max()
+        .map(|v| v.to_string())
+        .ok_or_else(|| anyhow!("No version matches \"{}\".", req))
+}
+
+/// Convert an npm version range, such as `>= 1.2 < 2 || ^3.0.0`, into the
+/// equivalent requirements of the `semver` crate.
+fn parse_range(range: &str) -> Result<Vec<VersionReq>, AnyError> {
+    range
+        .split("||")
+        .map(|set| {
+            let set = set.trim();
+            let comparators: Vec<String> = match set.split_once(" - ") {
+                Some((from, to)) => vec![
+                    format!(">={}", from.trim().trim_start_matches('v')),
+                    format!("<={}", to.trim().trim_start_matches('v')),
+                ],
+                None => {
+                    let mut comparators = Vec::new();
+                    let mut op = String::new();
+                    for token in set.split_whitespace() {
+                        if token.chars().all(|c| "<>=~^".contains(c)) {
+                            op.push_str(token);
+                            continue;
+                        }
+                        let token = format!("{}{}", op, token);
+                        op.clear();
+                        comparators.push(npm_comparator(&token));
+                    }
+                    comparators
+                }
+            };
+            if comparators.is_empty() {
+                return Ok(VersionReq::STAR);
+            }
+            VersionReq::parse(&comparators.join(", "))
+                .with_context(|| format!("Invalid npm version range \"{}\".", range))
+        })
+        .collect()
+}
+
+/// A bare version is an exact match in npm, but a caret requirement in the
+/// `semver` crate.
+fn npm_comparator(comparator: &str) -> String {
+    let version = comparator.trim_start_matches(|c| "<>=~^".contains(c));
+    let op = &comparator[..comparator.len() - version.len()];
+    let version = version.trim_start_matches('v');
+    let is_wildcard = version.contains(['x', 'X', '*']);
+    if op.is_empty() && !is_wildcard {
+        format!("={}", version)
+    } else {
+        format!("{}{}", op, version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn packument(versions: &[&str]) -> Packument {
+        Packument {
+            dist_tags: HashMap::from([("latest".to_string(), "10.16.0".to_string())]),
+            versions: versions
+                .iter()
+                .map(|v| {
+                    let version = PackumentVersion {
+                        dist: PackumentDist {
+                            tarball: format!(
+                                "https://registry.npmjs.org/preact/-/preact-{}.tgz",
+                                v
+                            ),
+                            integrity: None,
+                            shasum: None,
+                        },
+                    };
+                    (v.to_string(), version)
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn resolve_version_should_follow_npm_ranges() {
+        let packument = packument(&["8.5.3", "10.0.0", "10.16.0", "11.0.0-beta.1"]);
+        let resolve = |req| resolve_version(&packument, req).unwrap();
+        assert_eq!(resolve(None), "10.16.0");
+        assert_eq!(resolve(Some("^10.0.0")), "10.16.0");
+        assert_eq!(resolve(Some("10.0.0")), "10.0.0");
+        assert_eq!(resolve(Some("10.x")), "10.16.0");
+        assert_eq!(resolve(Some(">= 8 < 10")), "8.5.3");
+        assert_eq!(resolve(Some("8.0.0 - 10.0.0")), "10.0.0");
+        assert_eq!(resolve(Some("^7 || ^8")), "8.5.3");
+        assert!(resolve_version(&packument, Some("^12")).is_err());
+    }
+
+    #[test]
+    fn tarball_version_should_follow_npm_pack_names() {
+        assert_eq!(
+            tarball_version("preact", "preact-10.16.0.tgz"),
+            Some("10.16.0")
+        );
+        assert_eq!(
+            tarball_version("@preact/signals", "preact-signals-1.2.0.tgz"),
+            Some("1.2.0")
+        );
+        assert_eq!(tarball_version("preact", "preact-signals-1.2.0.tgz"), None);
+        assert_eq!(tarball_version("preact", "preact-10.16.0.tar"), None);
+    }
+
+    #[test]
+    fn tarballs_should_match_their_integrity() {
+        let dist = |integrity: Option<&str>, shasum: Option<&str>| PackumentDist {
+            tarball: "https://registry.npmjs.org/hello/-/hello-1.0.0.tgz".to_string(),
+            integrity: integrity.map(str::to_string),
+            shasum: shasum.map(str::to_string),
+        };
+        let sha512 = "sha512-m3HSJL1i83hdltRq0+o9czGb+8KJDKra4t/3JRlnPKcjI8PZm6XBHXx6zG4UuMXaDEZjR1wuXDre9G9zvN7AQw==";
+        let sha1 = "aaf4c61ddcc5e8a2dabede0f3b482cd9aea9434d";
+        assert!(dist(Some(sha512), None).check_integrity(b"hello").is_ok());
+        assert!(dist(Some(&format!("sha1-bogus {}", sha512)), None)
+            .check_integrity(b"hello")
+            .is_ok());
+        let err = dist(Some(sha512), None)
+            .check_integrity(b"hello!")
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("Integrity check failed"),
+            "{}",
+            err
+        );
+        assert!(dist(None, Some(sha1)).check_integrity(b"hello").is_ok());
+        assert!(dist(None, Some(sha1)).check_integrity(b"hello!").is_err());
+        assert!(dist(None, None).check_integrity(b"hello").is_ok());
+    }
+}
@@ -0,0 +1,181 @@
+use deno_core::{
+    anyhow::{anyhow, Context},
+    error::AnyError,
+    resolve_import,
+    serde_json::Value,
+    ModuleSpecifier,
+};
+use deno_graph::source::{ResolveError, Resolver};
+use import_map::ImportMap;
+use std::{path::Path, sync::Arc};
+
+use crate::utils::{
+    node::{is_node_builtin, NodeBuiltins},
+    npm::NpmResolver,
+    resolve_from_cwd,
+};
+
+/// Resolves module specifiers, applying an import map when one is configured.
+///
+/// The same resolver is used when building the module graph for bundling and
+/// when loading modules at runtime, so both see the same modules.
+#[derive(Debug, Clone, Default)]
+pub struct ModuleResolver {
+    import_map: Option<Arc<ImportMap>>,
+    npm: Option<Arc<NpmResolver>>,
+    node_builtins: Option<Arc<NodeBuiltins>>,
+}
+
+impl ModuleResolver {
+    pub fn new(import_map: Option<Arc<ImportMap>>) -> Self {
+        Self {
+            import_map,
+            npm: None,
+            node_builtins: None,
+        }
+    }
+
+    /// Resolve the bare imports of the files of npm packages with `npm`, which
+    /// should also load the `npm:` specifiers.
+    pub fn with_npm(mut self, npm: Arc<NpmResolver>) -> Self {
+        self.npm = Some(npm);
+        self
+    }
+
+    pub fn npm(&self) -> Option<&Arc<NpmResolver>> {
+        self.npm.as_ref()
+    }
+
+    /// Resolve `node:` specifiers, and the bare imports of Node.js built-ins by
+    /// npm packages, with `node_builtins`. Without any built-in, `node:`
+    /// specifiers are left as is, e.g. for externals like `node:`.
+    pub fn with_node_builtins(mut self, node_builtins: Arc<NodeBuiltins>) -> Self {
+        self.node_builtins = Some(node_builtins).filter(|builtins| !builtins.is_empty());
+        self
+    }
+
+    pub fn resolve(
+        &self,
+        specifier: &str,
+        referrer: &ModuleSpecifier,
+    ) -> Result<ModuleSpecifier, AnyError> {
+        if let Some(node_builtins) = self.node_builtins.as_ref() {
+            let is_builtin = specifier.starts_with("node:")
+                || (referrer.scheme() == "npm" && is_node_builtin(specifier));
+            if is_builtin {
+                return node_builtins.resolve(specifier);
+            }
+        }
+        if let Some(npm) = self.npm.as_ref() {
+            if let Some(resolved) = npm.resolve_package_import(specifier, referrer) {
+                return Ok(resolved);
+            }
+        }
+        match self.import_map.as_ref() {
+            Some(import_map) => Ok(import_map.resolve(specifier, referrer)?),
+            None => Ok(resolve_import(specifier, referrer.as_str())?),
+        }
+    }
+}
+
+impl Resolver for ModuleResolver {
+    fn resolve(
+        &self,
+        specifier: &str,
+        referrer: &ModuleSpecifier,
+    ) -> Result<ModuleSpecifier, ResolveError> {
+        ModuleResolver::resolve(self, specifier, referrer).map_err(ResolveError::Other)
+    }
+}
+
+/// Load an import map from a JSON file. Relative addresses in the map are
+/// resolved against the location of the file.
+pub fn load_import_map_file(path: &Path) -> Result<ImportMap, AnyError> {
+    let path = resolve_from_cwd(path)?;
+    let base_url = ModuleSpecifier::from_file_path(&path)
+        .map_err(|_| anyhow!("Invalid import map path {}", path.display()))?;
+    let json = std::fs::read_to_string(&path)
+        .with_context(|| format!("Unable to read import map {}", path.display()))?;
+    parse_import_map(&base_url, &json)
+}
+
+/// Load an import map from a JSON value. Relative addresses in the map are
+/// resolved against `base_url`.
+pub fn load_import_map_value(
+    base_url: &ModuleSpecifier,
+    value: &Value,
+) -> Result<ImportMap, AnyError> {
+    parse_import_map(base_url, &value.to_string())
+}
+
+fn parse_import_map(base_url: &ModuleSpecifier, json: &str) -> Result<ImportMap, AnyError> {
+    let result = import_map::parse_from_json(base_url, json)
+        .with_context(|| format!("Invalid import map {}", base_url))?;
+    Ok(result.import_map)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::node::NodeBuiltin;
+    use deno_core::serde_json::json;
+
+    #[test]
+    fn import_map_should_resolve_bare_and_scoped_specifiers() {
+        let base_url = ModuleSpecifier::parse("file:///app/import_map.json").unwrap();
+        let import_map = load_import_map_value(
+            &base_url,
+            &json!({
+              "imports": {
+                "preact": "https://esm.sh/preact@10.16.0",
+                "@std/http/": "https://deno.land/std@0.196.0/http/"
+              },
+              "scopes": {
+                "./legacy/": {
+                  "preact": "https://esm.sh/preact@8.5.3"
+                }
+              }
+            }),
+        )
+        .unwrap();
+        let resolver = ModuleResolver::new(Some(Arc::new(import_map)));
+
+        let main = ModuleSpecifier::parse("file:///app/main.ts").unwrap();
+        let legacy = ModuleSpecifier::parse("file:///app/legacy/main.ts").unwrap();
+        assert_eq!(
+            resolver.resolve("preact", &main).unwrap().as_str(),
+            "https://esm.sh/preact@10.16.0"
+        );
+        assert_eq!(
+            resolver.resolve("preact", &legacy).unwrap().as_str(),
+            "https://esm.sh/preact@8.5.3"
+        );
+        assert_eq!(
+            resolver
+                .resolve("@std/http/server.ts", &main)
+                .unwrap()
+                .as_str(),
+            "https://deno.land/std@0.196.0/http/server.ts"
+        );
+        assert_eq!(
+            resolver.resolve("./util.ts", &main).unwrap().as_str(),
+            "file:///app/util.ts"
+        );
+    }
+
+    #[test]
+    fn node_specifiers_should_be_left_as_is_without_builtins() {
+        let main = ModuleSpecifier::parse("file:///app/main.ts").unwrap();
+        let resolver =
+            ModuleResolver::default().with_node_builtins(Arc::new(NodeBuiltins::default()));
+        let resolved = resolver.resolve("node:fs", &main).unwrap();
+        assert_eq!(resolved.as_str(), "node:fs");
+        let externals = crate::bundler::external::Externals::new(vec!["node:".to_string()]);
+        assert!(externals.matches(resolved.as_str()));
+
+        let resolver = ModuleResolver::default().with_node_builtins(Arc::new(
+            NodeBuiltins::default().with("path", NodeBuiltin::External),
+        ));
+        assert!(resolver.resolve("node:fs", &main).is_err());
+    }
+}
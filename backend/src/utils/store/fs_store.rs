@@ -1,8 +1,9 @@
 use super::FsModuleStore;
 use crate::utils::fs_util::to_hash_path;
+use crate::utils::http_cache::HttpMetadata;
 use crate::utils::ModuleStore;
 use async_trait::async_trait;
-use deno_core::{anyhow::bail, error::AnyError};
+use deno_core::{anyhow::bail, error::AnyError, serde_json};
 use dirs::home_dir;
 use std::{
     fs,
@@ -46,11 +47,28 @@ impl ModuleStore for FsModuleStore {
         file.write_all(value)?;
         Ok(())
     }
+
+    async fn get_metadata(&self, key: &str) -> Result<Option<HttpMetadata>, AnyError> {
+        let path = to_hash_path(&self.base, key).with_extension("metadata.json");
+        if !path.exists() {
+            return Ok(None);
+        }
+        let json = fs::read_to_string(&path)?;
+        Ok(serde_json::from_str(&json).ok())
+    }
+
+    async fn put_metadata(&self, key: String, metadata: &HttpMetadata) -> Result<(), AnyError> {
+        let path = to_hash_path(&self.base, &key).with_extension("metadata.json");
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, serde_json::to_string(metadata)?)?;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::FsModuleStore;
+    use crate::utils::http_cache::HttpMetadata;
     use crate::utils::ModuleStore;
     use std::path::PathBuf;
 
@@ -62,4 +80,26 @@ mod tests {
         let contents = store.get("foo").await.unwrap();
         assert_eq!(&contents[..], b"bar");
     }
+
+    #[tokio::test]
+    async fn module_store_should_keep_http_metadata() {
+        let base = PathBuf::from("/tmp/deno_fs_store");
+        let store = FsModuleStore::new(base);
+        let metadata = HttpMetadata {
+            url: "https://deno.land/std/http/server.ts".to_string(),
+            etag: Some("\"abc\"".to_string()),
+            fetched_at: 1000,
+            ..Default::default()
+        };
+        store
+            .put_metadata("https://deno.land/x/server.ts".to_string(), &metadata)
+            .await
+            .unwrap();
+        let stored = store
+            .get_metadata("https://deno.land/x/server.ts")
+            .await
+            .unwrap();
+        assert_eq!(stored, Some(metadata));
+        assert_eq!(store.get_metadata("bar").await.unwrap(), None);
+    }
 }